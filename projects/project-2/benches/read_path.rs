@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use project_2::KvStore;
+use tempfile::TempDir;
+
+const KEYS: usize = 1_000;
+
+fn read_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+
+    for mmap in [false, true] {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let store = KvStore::open(temp_dir.path()).unwrap();
+
+        for key_id in 0..KEYS {
+            store
+                .set(&format!("key{}", key_id), &"x".repeat(100))
+                .unwrap();
+        }
+
+        // Reopen so every record lives in a sealed segment.
+        drop(store);
+        let store = KvStore::options().mmap(mmap).open(temp_dir.path()).unwrap();
+        let name = if mmap { "mmap" } else { "read_at" };
+
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            let mut key_id = 0;
+
+            b.iter(|| {
+                key_id = (key_id + 1) % KEYS;
+                store.get(&format!("key{}", key_id)).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, read_path);
+criterion_main!(benches);
@@ -0,0 +1,290 @@
+use crate::{
+    backup::{checksum, stage_restore},
+    engines::Engine,
+    log::{LogRecords, Lsn},
+    utils::get_log_path,
+    KvsError, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+
+pub const ARCHIVE_INDEX: &str = "index.jsonl";
+
+/// An entry in an archive's index, written once the segment is safely
+/// copied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedSegment {
+    pub seq: u64,
+    /// When the segment was last written to, in seconds since the Unix
+    /// epoch.
+    pub sealed_at: u64,
+    pub size: u64,
+    pub crc32: u32,
+}
+
+/// A line in an archive's index.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum IndexEntry {
+    Archived(ArchivedSegment),
+    /// A sequence number taken for a compaction's commit before the
+    /// compaction starts, so that a gap left by aborting it can be told
+    /// apart from a segment that was lost.
+    Reserved {
+        reserved: u64,
+    },
+}
+
+/// The contents of an archive's index.
+#[derive(Debug, Default)]
+pub(crate) struct ArchiveIndex {
+    /// In segment order.
+    pub(crate) segments: Vec<ArchivedSegment>,
+    pub(crate) reserved: BTreeSet<u64>,
+}
+
+/// How far [`KvStore::restore_archive`](crate::KvStore::restore_archive)
+/// replays an archive. Parsed from `SEQ:OFFSET` for an LSN, or from a Unix
+/// timestamp in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePoint {
+    /// Everything written before the LSN.
+    Lsn(Lsn),
+    /// Every archived segment last written to at or before the time, as
+    /// given by the segment file's modification time when it was archived.
+    /// Records carry no timestamps, so this only restores whole segments.
+    Time(u64),
+}
+
+impl FromStr for RestorePoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.contains(':') {
+            true => s.parse().map(Self::Lsn),
+            false => s
+                .parse()
+                .map(Self::Time)
+                .map_err(|_| format!("expected SEQ:OFFSET or a Unix timestamp, got {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for RestorePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lsn(lsn) => write!(f, "LSN {lsn}"),
+            Self::Time(secs) => write!(f, "time {secs}"),
+        }
+    }
+}
+
+/// Copies sealed segments into an archive directory as the log seals them.
+#[derive(Debug)]
+pub(crate) struct Archive {
+    dir: PathBuf,
+    index: File,
+    archived: BTreeSet<u64>,
+}
+
+impl Archive {
+    pub(crate) fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let archived = read_index(dir)?
+            .segments
+            .iter()
+            .map(|segment| segment.seq)
+            .collect();
+
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(ARCHIVE_INDEX))
+            .map_err(KvsError::OpenFile)?;
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            index,
+            archived,
+        })
+    }
+
+    /// Copies a sealed segment into the archive, unless it is there already.
+    pub(crate) fn archive_segment(&mut self, log_path: &Path, seq: u64) -> Result<()> {
+        if self.archived.contains(&seq) {
+            return Ok(());
+        }
+
+        let source = get_log_path(log_path, seq);
+        // The last write to a sealed segment is the closest thing to when it
+        // was sealed, even if it is only archived on a later open.
+        let sealed_at = fs::metadata(&source)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        let target = get_log_path(&self.dir, seq);
+        let temp_path = target.with_extension("log.tmp");
+        fs::copy(&source, &temp_path)?;
+        File::open(&temp_path)?.sync_all()?;
+        fs::rename(&temp_path, &target)?;
+
+        let (size, crc32) = checksum(&target)?;
+        let segment = ArchivedSegment {
+            seq,
+            sealed_at,
+            size,
+            crc32,
+        };
+
+        self.append_entry(&IndexEntry::Archived(segment))?;
+
+        self.archived.insert(seq);
+        Ok(())
+    }
+
+    /// Records that the log may never seal a segment under `seq`.
+    pub(crate) fn reserve_segment(&mut self, seq: u64) -> Result<()> {
+        self.append_entry(&IndexEntry::Reserved { reserved: seq })
+    }
+
+    pub(crate) fn is_archived(&self, seq: u64) -> bool {
+        self.archived.contains(&seq)
+    }
+
+    fn append_entry(&mut self, entry: &IndexEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry).map_err(KvsError::InvalidManifest)?;
+        line.push(b'\n');
+        self.index.write_all(&line)?;
+        self.index.sync_data()?;
+
+        Ok(())
+    }
+}
+
+/// Reads an archive's index. A line left unfinished by a crash is cut off,
+/// as its segment was never fully archived.
+pub(crate) fn read_index(dir: &Path) -> Result<ArchiveIndex> {
+    let path = dir.join(ARCHIVE_INDEX);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(KvsError::OpenFile(e)),
+    };
+
+    let complete = content.rfind('\n').map_or(0, |end| end + 1);
+    if complete < content.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(complete as u64)?;
+    }
+
+    let mut index = ArchiveIndex::default();
+    for line in content[..complete].lines() {
+        match serde_json::from_str(line).map_err(KvsError::InvalidManifest)? {
+            IndexEntry::Archived(segment) => index.segments.push(segment),
+            IndexEntry::Reserved { reserved } => {
+                index.reserved.insert(reserved);
+            }
+        }
+    }
+    index.segments.sort_by_key(|segment| segment.seq);
+
+    Ok(index)
+}
+
+/// Builds a fresh store at `path` from the archive in `archive`, replaying
+/// it up to `until`. Returns the LSN the store was restored to.
+pub(crate) fn restore_archive(archive: &Path, path: &Path, until: RestorePoint) -> Result<Lsn> {
+    let ArchiveIndex { segments, reserved } = read_index(archive)?;
+    let unreachable = || KvsError::RestorePointNotFound(until.to_string());
+
+    let (segments, end) = match until {
+        RestorePoint::Lsn(lsn) => {
+            let last = segments
+                .iter()
+                .position(|segment| segment.seq == lsn.seq && lsn.offset <= segment.size)
+                .ok_or_else(unreachable)?;
+            (&segments[..=last], lsn)
+        }
+        RestorePoint::Time(secs) => {
+            let count = segments
+                .iter()
+                .take_while(|segment| segment.sealed_at <= secs)
+                .count();
+            let last = count.checked_sub(1).ok_or_else(unreachable)?;
+            let end = Lsn {
+                seq: segments[last].seq,
+                offset: segments[last].size,
+            };
+            (&segments[..count], end)
+        }
+    };
+    // Aborted compactions leave reserved gaps; any other gap is a segment
+    // that never made it into the archive.
+    for pair in segments.windows(2) {
+        if let Some(seq) = (pair[0].seq + 1..pair[1].seq).find(|seq| !reserved.contains(seq)) {
+            return Err(KvsError::NotArchived(seq));
+        }
+    }
+    for segment in segments {
+        verify_segment(archive, segment)?;
+    }
+    let truncate = segments
+        .last()
+        .is_some_and(|segment| end.offset < segment.size);
+    if truncate && !is_record_boundary(&get_log_path(archive, end.seq), end.offset)? {
+        return Err(unreachable());
+    }
+
+    if Engine::recorded(path)?.is_some() {
+        return Err(KvsError::StoreExists(path.to_owned()));
+    }
+    stage_restore(path, |staging| {
+        for segment in segments {
+            let target = get_log_path(staging, segment.seq);
+            fs::copy(get_log_path(archive, segment.seq), &target)?;
+            verify_segment(staging, segment)?;
+
+            if segment.seq == end.seq && truncate {
+                OpenOptions::new()
+                    .write(true)
+                    .open(&target)?
+                    .set_len(end.offset)?;
+            }
+            File::open(&target)?.sync_all()?;
+        }
+        Ok(segments.iter().map(|segment| segment.seq).collect())
+    })?;
+
+    Ok(end)
+}
+
+fn verify_segment(dir: &Path, segment: &ArchivedSegment) -> Result<()> {
+    let path = get_log_path(dir, segment.seq);
+    if checksum(&path)? != (segment.size, segment.crc32) {
+        return Err(KvsError::ChecksumMismatch(path));
+    }
+
+    Ok(())
+}
+
+fn is_record_boundary(path: &Path, offset: u64) -> Result<bool> {
+    for record in LogRecords::new(BufReader::new(File::open(path)?))? {
+        let record = record?;
+        if record.offset >= offset {
+            return Ok(record.offset == offset);
+        }
+    }
+
+    Ok(false)
+}
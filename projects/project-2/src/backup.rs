@@ -0,0 +1,377 @@
+use crate::{
+    engines::Engine,
+    log::Lsn,
+    utils::{get_log_path, lock_dir_exclusive, remove_log_file, scan_log_seqs, LOCK_FILE},
+    KvsError, OsVfs, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub(crate) const RESTORE_DIR: &str = ".restore";
+pub(crate) const REPLACED_DIR: &str = ".replaced";
+
+/// Describes a backup written by [`KvStore::backup_to`](crate::KvStore::backup_to).
+/// It is stored next to the backed-up segments as `manifest.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub id: String,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    /// The end of the log when the backup was taken. The backup holds every
+    /// record before it and nothing after it.
+    pub lsn: Lsn,
+    /// The segments stored in this backup.
+    pub segments: Vec<BackupSegment>,
+    /// The backup an incremental backup was taken on top of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Segments an incremental backup needs that are stored by its ancestors.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inherited: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupSegment {
+    pub seq: u64,
+    pub size: u64,
+    pub crc32: u32,
+}
+
+impl BackupManifest {
+    pub fn read(dir: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(dir.as_ref().join(MANIFEST_FILE)).map_err(KvsError::OpenFile)?;
+        serde_json::from_reader(BufReader::new(file)).map_err(KvsError::InvalidManifest)
+    }
+
+    /// Checks every segment in `dir` against the size and checksum recorded
+    /// for it.
+    pub fn verify(&self, dir: impl AsRef<Path>) -> Result<()> {
+        self.segments
+            .iter()
+            .try_for_each(|segment| verify_segment(dir.as_ref(), segment))
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.size).sum()
+    }
+
+    pub fn is_incremental(&self) -> bool {
+        self.parent.is_some()
+    }
+
+    /// Every segment the store held when the backup was taken.
+    fn covers(&self, seq: u64) -> bool {
+        self.inherited.contains(&seq) || self.segments.iter().any(|segment| segment.seq == seq)
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        let temp_path = dir.join(format!("{MANIFEST_FILE}.tmp"));
+        let file = File::create(&temp_path)?;
+        serde_json::to_writer_pretty(&file, self).map_err(io::Error::from)?;
+        file.sync_all()?;
+
+        fs::rename(temp_path, dir.join(MANIFEST_FILE))?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+}
+
+/// A frozen segment on its way into a backup. Segments that could not be
+/// hard-linked are held open, so compaction can remove them from the store
+/// while they are still being copied.
+pub(crate) struct FrozenSegment {
+    seq: u64,
+    pending: Option<File>,
+}
+
+/// Finds the backup with the given id among the directories next to `dir`.
+pub(crate) fn find_sibling_backup(dir: &Path, id: &str) -> Result<(PathBuf, BackupManifest)> {
+    let root = match dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if let Ok(manifest) = BackupManifest::read(&path) {
+            if manifest.id == id {
+                return Ok((path, manifest));
+            }
+        }
+    }
+
+    Err(KvsError::BackupNotFound(id.to_owned()))
+}
+
+/// Splits the segments of a frozen log into those an incremental backup on
+/// top of `parent` has to store, and those it can inherit. Sealed segments
+/// never change, so everything up to the parent's LSN is already covered.
+pub(crate) fn split_incremental(
+    parent: &BackupManifest,
+    seqs: &[u64],
+) -> Result<(Vec<u64>, Vec<u64>)> {
+    let (inherited, new): (Vec<u64>, Vec<u64>) =
+        seqs.iter().partition(|&&seq| seq <= parent.lsn.seq);
+
+    match inherited.iter().find(|&&seq| !parent.covers(seq)) {
+        Some(&seq) => Err(KvsError::IncompleteBackup {
+            id: parent.id.clone(),
+            seq,
+        }),
+        None => Ok((new, inherited)),
+    }
+}
+
+/// Fails unless `dir` is missing or empty.
+pub(crate) fn prepare_backup_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    match fs::read_dir(dir)?.next() {
+        Some(_) => Err(KvsError::BackupExists(dir.to_owned())),
+        None => Ok(()),
+    }
+}
+
+/// Links each sealed segment into the backup, or opens it to copy later.
+/// This is the only step that has to run under the store's write lock.
+pub(crate) fn freeze_segments(
+    log_path: &Path,
+    seqs: &[u64],
+    dir: &Path,
+) -> Result<Vec<FrozenSegment>> {
+    seqs.iter()
+        .map(|&seq| {
+            let source = get_log_path(log_path, seq);
+            let pending = match fs::hard_link(&source, get_log_path(dir, seq)) {
+                Ok(()) => None,
+                Err(_) => Some(File::open(&source)?),
+            };
+
+            Ok(FrozenSegment { seq, pending })
+        })
+        .collect()
+}
+
+/// Copies any segments that could not be linked, then checksums them all
+/// and writes the manifest. Runs without holding the store's write lock.
+pub(crate) fn finish_backup(
+    dir: &Path,
+    lsn: Lsn,
+    frozen: Vec<FrozenSegment>,
+    parent: Option<String>,
+    inherited: Vec<u64>,
+) -> Result<BackupManifest> {
+    let mut segments = Vec::new();
+
+    for FrozenSegment { seq, pending } in frozen {
+        let path = get_log_path(dir, seq);
+        if let Some(mut source) = pending {
+            let mut target = File::create(&path)?;
+            io::copy(&mut source, &mut target)?;
+            target.sync_all()?;
+        }
+
+        let (size, crc32) = checksum(&path)?;
+        segments.push(BackupSegment { seq, size, crc32 });
+    }
+
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let manifest = BackupManifest {
+        id: format!("{created_at}-{}", lsn.seq),
+        created_at,
+        lsn,
+        segments,
+        parent,
+        inherited,
+    };
+    manifest.write(dir)?;
+
+    Ok(manifest)
+}
+
+/// Replaces the store at `path` with the backup in `backup`, pulling the
+/// segments an incremental backup inherits from its ancestors. The segments
+/// are staged and verified inside the store directory first, so a corrupt
+/// backup leaves the store untouched.
+pub(crate) fn restore(backup: &Path, path: &Path) -> Result<BackupManifest> {
+    let manifest = BackupManifest::read(backup)?;
+    let chain = backup_chain(backup, &manifest)?;
+
+    let mut sources = Vec::new();
+    for seq in manifest
+        .segments
+        .iter()
+        .map(|s| s.seq)
+        .chain(manifest.inherited.iter().copied())
+    {
+        let source = chain.iter().find_map(|(dir, ancestor)| {
+            let segment = ancestor
+                .segments
+                .iter()
+                .find(|segment| segment.seq == seq)?;
+            Some((dir.as_path(), segment))
+        });
+        match source {
+            Some(source) => sources.push(source),
+            None => {
+                return Err(KvsError::IncompleteBackup {
+                    id: manifest.id.clone(),
+                    seq,
+                })
+            }
+        }
+    }
+    for (dir, segment) in &sources {
+        verify_segment(dir, segment)?;
+    }
+
+    stage_restore(path, |staging| {
+        for (dir, segment) in &sources {
+            let target = get_log_path(staging, segment.seq);
+            fs::copy(get_log_path(dir, segment.seq), &target)?;
+            File::open(&target)?.sync_all()?;
+            verify_segment(staging, segment)?;
+        }
+        Ok(sources.iter().map(|(_, segment)| segment.seq).collect())
+    })?;
+
+    Ok(manifest)
+}
+
+/// Restores segments into `path` through a staging directory. `stage` fills
+/// the staging directory and returns the segments it holds. Any segments
+/// already in `path` are moved aside, the staged ones moved into place and
+/// the directory claimed, and only then are the old ones deleted. If
+/// anything fails, the swap is undone and whatever else the restore created
+/// goes, leaving `path` as it was found. A crash part way leaves the old
+/// segments in `.replaced`, which `verify` reports.
+pub(crate) fn stage_restore(
+    path: &Path,
+    stage: impl FnOnce(&Path) -> Result<Vec<u64>>,
+) -> Result<()> {
+    let existed = path.exists();
+    let had_lock = path.join(LOCK_FILE).exists();
+    fs::create_dir_all(path)?;
+    let locks = Engine::Kvs
+        .check_dir(path)
+        .and_then(|()| lock_dir_exclusive(path, None));
+    let _locks = match locks {
+        Ok(locks) => locks,
+        Err(e) => {
+            if !existed {
+                let _ = fs::remove_dir(path);
+            }
+            return Err(e);
+        }
+    };
+
+    let staging = path.join(RESTORE_DIR);
+    let replaced = path.join(REPLACED_DIR);
+    let mut moved_out = Vec::new();
+    let mut moved_in = Vec::new();
+
+    let result = (|| {
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir(&staging)?;
+        let seqs = stage(&staging)?;
+
+        // Fails if an earlier restore crashed with the old segments in there.
+        fs::create_dir(&replaced)?;
+        for seq in scan_log_seqs(&OsVfs, path)? {
+            fs::rename(get_log_path(path, seq), get_log_path(&replaced, seq))?;
+            moved_out.push(seq);
+        }
+        for seq in seqs {
+            fs::rename(get_log_path(&staging, seq), get_log_path(path, seq))?;
+            moved_in.push(seq);
+        }
+        fs::remove_dir(&staging)?;
+        File::open(path)?.sync_all()?;
+
+        Engine::Kvs.claim_dir(path)
+    })();
+
+    // Cleaned up while still holding the locks, so no one else can have
+    // moved in. Best effort: the original error is the one worth reporting.
+    match &result {
+        Ok(()) => {
+            let _ = fs::remove_dir_all(&replaced);
+        }
+        Err(_) if !existed => {
+            let _ = fs::remove_dir_all(path);
+        }
+        Err(_) => {
+            for &seq in &moved_in {
+                let _ = remove_log_file(&OsVfs, path, seq);
+            }
+            for &seq in &moved_out {
+                let _ = fs::rename(get_log_path(&replaced, seq), get_log_path(path, seq));
+            }
+            let _ = fs::remove_dir(&replaced);
+            let _ = fs::remove_dir_all(&staging);
+            if !had_lock {
+                let _ = fs::remove_file(path.join(LOCK_FILE));
+            }
+        }
+    }
+
+    result
+}
+
+/// Follows an incremental backup's parents back to a full backup, newest
+/// first.
+fn backup_chain(
+    backup: &Path,
+    manifest: &BackupManifest,
+) -> Result<Vec<(PathBuf, BackupManifest)>> {
+    let mut chain = vec![(backup.to_owned(), manifest.clone())];
+
+    while let Some(parent_id) = chain.last().and_then(|(_, last)| last.parent.clone()) {
+        let (dir, parent) = find_sibling_backup(backup, &parent_id)?;
+        // Parents are always older; anything else would send us round in circles.
+        if parent.lsn >= chain.last().unwrap().1.lsn {
+            return Err(KvsError::BackupNotFound(parent_id));
+        }
+        chain.push((dir, parent));
+    }
+
+    Ok(chain)
+}
+
+fn verify_segment(dir: &Path, segment: &BackupSegment) -> Result<()> {
+    let path = get_log_path(dir, segment.seq);
+    if checksum(&path)? != (segment.size, segment.crc32) {
+        return Err(KvsError::ChecksumMismatch(path));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn checksum(path: &Path) -> Result<(u64, u32)> {
+    let mut file = File::open(path).map_err(KvsError::OpenFile)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = [0; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => {
+                hasher.update(&buffer[..read]);
+                size += read as u64;
+            }
+        }
+    }
+
+    Ok((size, hasher.finalize()))
+}
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use clap::Parser;
+use project_2::{
+    log::{Keyspaces, LogCommand, LogPointer, LogRecords, DEFAULT_KEYSPACE},
+    utils::{build_index, get_log_path, open_log_readers, scan_log_seqs},
+    OsVfs,
+};
+use serde::Serialize;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// Lists the segments of a kvs store and decodes the records in them. The
+/// store is read without taking its lock, so it can be inspected while in use.
+#[derive(Debug, Parser)]
+#[command(name = "kvs-inspect", version, about)]
+struct Args {
+    /// The store directory. Defaults to the current directory.
+    #[arg(env = "KVS_DIR")]
+    dir: Option<PathBuf>,
+    /// Only inspect the given segments.
+    #[arg(long = "segment", value_name = "SEQ")]
+    segments: Vec<u64>,
+    /// List segments without their records.
+    #[arg(long)]
+    summary: bool,
+    /// Truncate values to this many characters; 0 prints them whole.
+    #[arg(long, value_name = "CHARS", default_value_t = 32)]
+    value_width: usize,
+    /// Print a JSON document instead of a table.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SegmentReport {
+    seq: u64,
+    size: u64,
+    records: usize,
+    live: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<DecodeError>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<RecordReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct RecordReport {
+    offset: u64,
+    length: u64,
+    command: &'static str,
+    keyspace: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_bytes: Option<usize>,
+    live: bool,
+}
+
+/// Where decoding a segment gave up, usually on a record torn by a crash.
+#[derive(Debug, Serialize)]
+struct DecodeError {
+    offset: u64,
+    message: String,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let dir = match &args.dir {
+        Some(dir) => dir.clone(),
+        None => std::env::current_dir()?,
+    };
+
+    let seqs = scan_log_seqs(&OsVfs, &dir)?;
+    let (_, index) = build_index(&mut open_log_readers(&OsVfs, &dir, &seqs)?)?;
+
+    let reports = seqs
+        .into_iter()
+        .filter(|seq| args.segments.is_empty() || args.segments.contains(seq))
+        .map(|seq| inspect_segment(&dir, seq, &index, args.value_width))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut stdout = io::stdout().lock();
+    match args.json {
+        true => {
+            let reports = match args.summary {
+                true => strip_entries(reports),
+                false => reports,
+            };
+            serde_json::to_writer_pretty(&mut stdout, &serde_json::json!({ "segments": reports }))?;
+            writeln!(stdout)?;
+        }
+        false => print_table(&mut stdout, &reports, args.summary)?,
+    }
+
+    Ok(())
+}
+
+fn inspect_segment(
+    dir: &Path,
+    seq: u64,
+    index: &Keyspaces,
+    value_width: usize,
+) -> Result<SegmentReport> {
+    let path = get_log_path(dir, seq);
+    let size = fs::metadata(&path)?.len();
+    let mut entries = Vec::new();
+    let mut error = None;
+
+    for record in LogRecords::new(BufReader::new(File::open(&path)?))? {
+        match record {
+            Ok(record) => {
+                let pointer = LogPointer::new(seq, record.offset, record.length);
+                entries.push(describe(record.command, &pointer, index, value_width));
+            }
+            Err(e) => {
+                let offset = entries
+                    .last()
+                    .map_or(0, |last: &RecordReport| last.offset + last.length);
+                error = Some(DecodeError {
+                    offset,
+                    message: error_chain(&e),
+                });
+            }
+        }
+    }
+
+    Ok(SegmentReport {
+        seq,
+        size,
+        records: entries.len(),
+        live: entries.iter().filter(|entry| entry.live).count(),
+        error,
+        entries,
+    })
+}
+
+fn describe(
+    command: LogCommand,
+    pointer: &LogPointer,
+    index: &Keyspaces,
+    value_width: usize,
+) -> RecordReport {
+    let (command, keyspace, key, value) = match command {
+        LogCommand::Set(key, value) => ("set", DEFAULT_KEYSPACE.to_owned(), Some(key), Some(value)),
+        LogCommand::KeyspaceSet(keyspace, key, value) => ("set", keyspace, Some(key), Some(value)),
+        LogCommand::Remove(key) => ("remove", DEFAULT_KEYSPACE.to_owned(), Some(key), None),
+        LogCommand::KeyspaceRemove(keyspace, key) => ("remove", keyspace, Some(key), None),
+        LogCommand::DropKeyspace(keyspace) => ("drop_keyspace", keyspace, None, None),
+    };
+
+    // Only sets are ever live: a record is live when the index still points at it.
+    let live = value.is_some()
+        && key.as_ref().is_some_and(|key| {
+            index.get(&keyspace).is_some_and(|keys| {
+                keys.value()
+                    .get(key)
+                    .is_some_and(|entry| *entry.value().read().unwrap() == *pointer)
+            })
+        });
+
+    RecordReport {
+        offset: pointer.offset,
+        length: pointer.length,
+        command,
+        keyspace,
+        key,
+        value_bytes: value.as_ref().map(String::len),
+        value: value.map(|value| truncate(value, value_width)),
+        live,
+    }
+}
+
+fn truncate(value: String, width: usize) -> String {
+    match width > 0 && value.chars().count() > width {
+        true => value.chars().take(width).chain(['…']).collect(),
+        false => value,
+    }
+}
+
+fn strip_entries(reports: Vec<SegmentReport>) -> Vec<SegmentReport> {
+    reports
+        .into_iter()
+        .map(|report| SegmentReport {
+            entries: Vec::new(),
+            ..report
+        })
+        .collect()
+}
+
+fn print_table(out: &mut impl Write, reports: &[SegmentReport], summary: bool) -> io::Result<()> {
+    for report in reports {
+        writeln!(
+            out,
+            "{}.log\t{} bytes\t{} records\t{} live",
+            report.seq, report.size, report.records, report.live
+        )?;
+        if summary {
+            continue;
+        }
+
+        for entry in &report.entries {
+            writeln!(
+                out,
+                "  {:>10} {:>6} {:<4} {:<13} {}\t{}\t{}",
+                entry.offset,
+                entry.length,
+                if entry.live { "live" } else { "" },
+                entry.command,
+                entry.keyspace,
+                entry.key.as_deref().unwrap_or(""),
+                entry.value.as_deref().map(escape).unwrap_or_default(),
+            )?;
+        }
+        if let Some(error) = &report.error {
+            writeln!(out, "  {:>10} undecodable: {}", error.offset, error.message)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps each record on one line of the table.
+fn escape(value: &str) -> String {
+    value.escape_debug().to_string()
+}
+
+fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message += &format!(": {cause}");
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,162 @@
+use crate::log::LogPointer;
+use lru::LruCache;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+type CacheKey = (String, String);
+
+#[derive(Debug)]
+struct CachedValue {
+    pointer: LogPointer,
+    value: String,
+}
+
+#[derive(Debug)]
+struct CacheEntries {
+    entries: LruCache<CacheKey, CachedValue>,
+    size_bytes: u64,
+}
+
+/// A bounded LRU cache of values, sized by the bytes of the keys and values
+/// it holds. Every entry remembers the log pointer its value was read from,
+/// and only counts as a hit while the index still points there, so a read
+/// racing with a write can never leave a stale value behind.
+#[derive(Debug)]
+pub struct ValueCache {
+    capacity_bytes: u64,
+    inner: Mutex<CacheEntries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    pub capacity_bytes: u64,
+    pub size_bytes: u64,
+    pub entries: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// The fraction of lookups served from the cache, or `0.0` before any lookups.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+fn entry_size(key: &CacheKey, value: &str) -> u64 {
+    (key.0.len() + key.1.len() + value.len()) as u64
+}
+
+impl ValueCache {
+    pub fn new(capacity_bytes: u64) -> Self {
+        let inner = CacheEntries {
+            entries: LruCache::unbounded(),
+            size_bytes: 0,
+        };
+
+        Self {
+            capacity_bytes,
+            inner: Mutex::new(inner),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, keyspace: &str, key: &str, pointer: &LogPointer) -> Option<String> {
+        let cache_key = (keyspace.to_owned(), key.to_owned());
+        let mut inner = self.inner.lock().unwrap();
+
+        let value = match inner.entries.get(&cache_key) {
+            Some(cached) if cached.pointer == *pointer => Some(cached.value.clone()),
+            _ => None,
+        };
+
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        value
+    }
+
+    pub fn insert(&self, keyspace: &str, key: &str, pointer: LogPointer, value: String) {
+        let cache_key = (keyspace.to_owned(), key.to_owned());
+        let size = entry_size(&cache_key, &value);
+
+        if size > self.capacity_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let cached = CachedValue { pointer, value };
+
+        if let Some((key, prev)) = inner.entries.push(cache_key, cached) {
+            inner.size_bytes -= entry_size(&key, &prev.value);
+        }
+        inner.size_bytes += size;
+
+        while inner.size_bytes > self.capacity_bytes {
+            match inner.entries.pop_lru() {
+                Some((key, evicted)) => inner.size_bytes -= entry_size(&key, &evicted.value),
+                None => break,
+            }
+        }
+    }
+
+    pub fn invalidate(&self, keyspace: &str, key: &str) {
+        let cache_key = (keyspace.to_owned(), key.to_owned());
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(prev) = inner.entries.pop(&cache_key) {
+            inner.size_bytes -= entry_size(&cache_key, &prev.value);
+        }
+    }
+
+    pub fn invalidate_keyspace(&self, keyspace: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let keys = inner
+            .entries
+            .iter()
+            .filter(|(key, _)| key.0 == keyspace)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            if let Some(prev) = inner.entries.pop(&key) {
+                inner.size_bytes -= entry_size(&key, &prev.value);
+            }
+        }
+    }
+
+    /// Follows a record that compaction moved, so the cached value stays
+    /// valid. Entries already out of date are left to miss.
+    pub fn repoint(&self, keyspace: &str, key: &str, old: &LogPointer, new: &LogPointer) {
+        let cache_key = (keyspace.to_owned(), key.to_owned());
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(cached) = inner.entries.peek_mut(&cache_key) {
+            if cached.pointer == *old {
+                cached.pointer = new.clone();
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+
+        CacheStats {
+            capacity_bytes: self.capacity_bytes,
+            size_bytes: inner.size_bytes,
+            entries: inner.entries.len() as u64,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
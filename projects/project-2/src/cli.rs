@@ -1,14 +1,126 @@
-use clap::{Args, Parser};
+use crate::{engines::Engine, log::DEFAULT_KEYSPACE, Format, RestorePoint};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
-pub enum Cli {
+pub struct Cli {
+    /// The data directory of the store. Defaults to the profile's directory,
+    /// then to the current directory.
+    #[arg(long, global = true, env = "KVS_DIR", value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// The profile to use from the user's kvs/config.toml.
+    #[arg(long, global = true, env = "KVS_PROFILE", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Create a new store even if the directory already holds other files.
+    #[arg(long, global = true)]
+    pub init: bool,
+
+    /// The storage engine to use. Defaults to the profile's engine, then to kvs.
+    #[arg(long, global = true, value_enum)]
+    pub engine: Option<Engine>,
+
+    /// The keyspace to operate on.
+    #[arg(long, global = true, default_value = DEFAULT_KEYSPACE)]
+    pub keyspace: String,
+
+    /// Read store options from a TOML file. Command-line flags take precedence.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Seconds to wait for another process to release the store before giving up.
+    #[arg(long, global = true, value_name = "SECONDS")]
+    pub wait: Option<u64>,
+
+    /// How to print results and errors.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Plain)]
+    pub output: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text, one item per line.
+    Plain,
+    /// A single JSON document per command; errors are JSON objects on stderr.
+    Json,
+    /// Like plain, but values are printed exactly as stored, with no newline.
+    Raw,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
     /// Set the value of a string key to a string.
     Set(SetArgs),
     // Get the string value of a given string key.
     Get(GetArgs),
     /// Remove a given key.
     Rm(RmArgs),
+    /// List the keyspaces that hold at least one key.
+    Keyspaces,
+    /// Remove a keyspace and every key in it.
+    DropKeyspace(DropKeyspaceArgs),
+    /// List keys in sorted order.
+    Keys(KeysArgs),
+    /// Print keys and their values, separated by a tab.
+    Scan(PrefixArgs),
+    /// Print whether a key exists.
+    Exists(ExistsArgs),
+    /// Print the number of keys.
+    Count,
+    /// Print statistics about the store.
+    Stats,
+    /// Compact the log now, printing its size before and after.
+    Compact,
+    /// Write every key in every keyspace to stdout as JSON lines.
+    Dump,
+    /// Set every key read from stdin in the format written by `dump`.
+    Load,
+    /// Write every key in every keyspace to a file, or to stdout.
+    Export(ExportArgs),
+    /// Set every key read from a file, or from stdin, written by `export`.
+    Import(ImportArgs),
+    /// Check every segment and file in the store, without opening it. Exits
+    /// non-zero if anything is wrong.
+    Verify,
+    /// Salvage the records that still decode from damaged segments, moving
+    /// the damaged files and any others the store does not use to lost+found.
+    Repair,
+    /// Back up the store into an empty directory while it stays writable.
+    Backup(BackupArgs),
+    /// Replace the store with a backup, after verifying its checksums.
+    Restore(RestoreArgs),
+    /// Open the store once and run commands interactively.
+    Shell(ShellArgs),
+}
+
+impl Command {
+    /// Whether the command needs features only the kvs engine has.
+    pub fn requires_kvs(&self) -> bool {
+        !matches!(self, Self::Set(_) | Self::Get(_) | Self::Rm(_))
+    }
+
+    /// Whether the command only reads, so the store can be opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Get(_)
+                | Self::Keyspaces
+                | Self::Keys(KeysArgs { delete: false, .. })
+                | Self::Scan(_)
+                | Self::Exists(_)
+                | Self::Count
+                | Self::Stats
+                | Self::Dump
+                | Self::Export(_)
+                | Self::Verify
+        )
+    }
 }
 
 #[derive(Debug, Args)]
@@ -26,3 +138,100 @@ pub struct GetArgs {
 pub struct RmArgs {
     pub key: String,
 }
+
+#[derive(Debug, Args)]
+pub struct DropKeyspaceArgs {
+    pub name: String,
+}
+
+#[derive(Debug, Args)]
+pub struct KeysArgs {
+    /// Only include keys that start with this prefix.
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    /// Only include keys that match this regular expression.
+    #[arg(long, value_parser = Regex::new)]
+    pub regex: Option<Regex>,
+
+    /// Print each key's value after it, separated by a tab.
+    #[arg(long, conflicts_with = "delete")]
+    pub values: bool,
+
+    /// Remove every key matching --regex, one at a time under the write lock.
+    #[arg(long, requires = "regex", conflicts_with = "prefix")]
+    pub delete: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PrefixArgs {
+    /// Only include keys that start with this prefix.
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ExistsArgs {
+    pub key: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ShellArgs {
+    /// Run the commands in a file, one per line, instead of prompting.
+    #[arg(long, value_name = "FILE")]
+    pub batch: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// The file format. Defaults to the file's extension, then to jsonl.
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
+
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// The file format. Defaults to the file's extension, then to jsonl.
+    #[arg(long, value_enum)]
+    pub format: Option<Format>,
+
+    /// Import nothing if any key already exists, instead of overwriting.
+    #[arg(long)]
+    pub fail_on_conflict: bool,
+
+    pub file: Option<PathBuf>,
+}
+
+/// Picks the format given with `--format`, or the one implied by the file name.
+pub fn resolve_format(format: Option<Format>, file: Option<&Path>) -> Format {
+    format
+        .or_else(|| file.and_then(Format::from_path))
+        .unwrap_or(Format::JsonLines)
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Directory to write the backup to; must be missing or empty.
+    #[arg(value_name = "DIR")]
+    pub backup: PathBuf,
+    /// Only store the segments written since an earlier backup.
+    #[arg(long, requires = "since")]
+    pub incremental: bool,
+    /// Id of the backup to build on; it must sit in a directory next to DIR.
+    #[arg(long, value_name = "ID", requires = "incremental")]
+    pub since: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Directory holding the backup to restore, or the archive to replay
+    /// with --until.
+    #[arg(value_name = "DIR")]
+    pub source: PathBuf,
+    /// Replay an archive into a fresh store up to an LSN (SEQ:OFFSET) or a
+    /// Unix timestamp, instead of restoring a backup.
+    #[arg(long, value_name = "LSN|TIMESTAMP")]
+    pub until: Option<RestorePoint>,
+}
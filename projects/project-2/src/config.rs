@@ -0,0 +1,80 @@
+use crate::{engines::Engine, KvStoreOptions, KvsError, Result};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs, io::ErrorKind, path::Path, path::PathBuf};
+
+const DEFAULT_PROFILE: &str = "default";
+
+/// The user's `kvs/config.toml`, which holds named profiles:
+///
+/// ```toml
+/// [profiles.default]
+/// dir = "/var/lib/kvs"
+///
+/// [profiles.cache]
+/// dir = "/tmp/kvs-cache"
+/// engine = "sled"
+///
+/// [profiles.cache.options]
+/// cache_size = 1048576
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Where a store lives and how to open it. Every field is optional, and
+/// command-line flags take precedence over all of them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub dir: Option<PathBuf>,
+    pub engine: Option<Engine>,
+    pub options: KvStoreOptions,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/kvs/config.toml`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+        Some(config_home.join("kvs").join("config.toml"))
+    }
+
+    /// Loads the config from [`Config::default_path`]. A missing file is
+    /// the same as an empty one.
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(toml) => toml::from_str(&toml).map_err(KvsError::Config),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(KvsError::OpenFile(e)),
+        }
+    }
+
+    /// Looks up a profile by name. Without a name, the `default` profile is
+    /// used if there is one.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        match name {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| KvsError::ProfileNotFound(name.to_owned())),
+            None => Ok(self
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default()),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use super::KvsEngine;
+use crate::{KvsError, Result};
+use std::sync::{Arc, RwLock};
+
+/// An in-memory engine backed by project-1's `HashMap` store. Nothing is
+/// persisted, so every instance starts out empty.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryKvsEngine(Arc<RwLock<project_1::KvStore>>);
+
+impl MemoryKvsEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KvsEngine for MemoryKvsEngine {
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.write().unwrap().set(key, value);
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.0.read().unwrap().get(key).map(str::to_owned))
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let mut store = self.0.write().unwrap();
+
+        if store.get(key).is_none() {
+            return Err(KvsError::KeyNotFound);
+        }
+
+        store.remove(key);
+        Ok(())
+    }
+}
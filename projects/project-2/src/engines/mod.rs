@@ -0,0 +1,114 @@
+use crate::{utils::scan_log_seqs, KvsError, OsVfs, Result, Vfs};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::{
+    io::{ErrorKind, Read, Write},
+    path::Path,
+};
+
+mod memory;
+mod sled;
+
+pub use self::memory::MemoryKvsEngine;
+pub use self::sled::SledKvsEngine;
+
+pub(crate) const ENGINE_FILE: &str = "engine";
+
+/// A key/value storage engine. Engines are cheap handles that can be cloned
+/// and sent to other threads, with every clone sharing the same data.
+pub trait KvsEngine: Clone + Send + Sync + 'static {
+    /// Sets the value of a string key to a string.
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+
+    /// Gets the string value of a given string key, or `None` if it does not exist.
+    fn get(&self, key: &str) -> Result<Option<String>>;
+
+    /// Removes a given key, returning `KvsError::KeyNotFound` if it does not exist.
+    fn remove(&self, key: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Kvs,
+    Sled,
+    Memory,
+}
+
+impl Engine {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Kvs => "kvs",
+            Self::Sled => "sled",
+            Self::Memory => "memory",
+        }
+    }
+
+    /// Refuses data directories that were created by a different engine.
+    /// Directories holding log files but no engine record are treated as
+    /// belonging to `kvs`.
+    pub fn check_dir(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.check_dir_in(&OsVfs, path.as_ref())
+    }
+
+    pub(crate) fn check_dir_in(&self, vfs: &dyn Vfs, path: &Path) -> Result<()> {
+        match recorded_engine(vfs, path)? {
+            Some(recorded) if recorded != self.name() => Err(KvsError::WrongEngine {
+                recorded,
+                requested: self.name(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the engine that owns a data directory, or `None` if the
+    /// directory is missing or holds no data yet.
+    pub fn recorded(path: impl AsRef<Path>) -> Result<Option<String>> {
+        Self::recorded_in(&OsVfs, path.as_ref())
+    }
+
+    pub(crate) fn recorded_in(vfs: &dyn Vfs, path: &Path) -> Result<Option<String>> {
+        match vfs.is_dir(path) {
+            true => recorded_engine(vfs, path),
+            false => Ok(None),
+        }
+    }
+
+    /// Checks a data directory with [`Engine::check_dir`], then records this
+    /// engine in it.
+    pub fn claim_dir(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.claim_dir_in(&OsVfs, path.as_ref())
+    }
+
+    pub(crate) fn claim_dir_in(&self, vfs: &dyn Vfs, path: &Path) -> Result<()> {
+        self.check_dir_in(vfs, path)?;
+
+        let engine_path = path.join(ENGINE_FILE);
+        if !vfs.exists(&engine_path) {
+            // Written aside first, so a crash cannot leave an empty record behind.
+            let temp_path = engine_path.with_extension("tmp");
+            let mut file = vfs.create(&temp_path)?;
+            file.write_all(self.name().as_bytes())?;
+            file.sync_data()?;
+            vfs.rename(&temp_path, &engine_path)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn recorded_engine(vfs: &dyn Vfs, path: &Path) -> Result<Option<String>> {
+    let mut recorded = String::new();
+    let read = vfs
+        .open(&path.join(ENGINE_FILE))
+        .and_then(|mut file| file.read_to_string(&mut recorded));
+
+    match read {
+        Ok(_) => Ok(Some(recorded.trim().to_owned())),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let has_logs = !scan_log_seqs(vfs, path)?.is_empty();
+            Ok(has_logs.then(|| Engine::Kvs.name().to_owned()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,40 @@
+use super::{Engine, KvsEngine};
+use crate::{KvsError, Result};
+use sled::Db;
+use std::path::Path;
+
+/// An engine backed by the `sled` embedded database.
+#[derive(Debug, Clone)]
+pub struct SledKvsEngine(Db);
+
+impl SledKvsEngine {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path)?;
+        Engine::Sled.claim_dir(path)?;
+
+        Ok(Self(sled::open(path)?))
+    }
+}
+
+impl KvsEngine for SledKvsEngine {
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.0.insert(key, value.as_bytes())?;
+        self.0.flush()?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        self.0
+            .get(key)?
+            .map(|value| String::from_utf8(value.to_vec()))
+            .transpose()
+            .map_err(KvsError::from)
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.0.remove(key)?.ok_or(KvsError::KeyNotFound)?;
+        self.0.flush()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, KvsError>;
@@ -13,9 +14,229 @@ pub enum KvsError {
     #[error("Failed to read from log")]
     ReadFromLog(#[source] bincode::Error),
 
+    #[error("Failed to import record {record}")]
+    Import {
+        record: u64,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("A backup already exists at {0}")]
+    BackupExists(PathBuf),
+
+    #[error("No backup with id {0}")]
+    BackupNotFound(String),
+
+    #[error("The archive cannot be restored to {0}")]
+    RestorePointNotFound(String),
+
+    #[error("Backup {id} does not cover log segment {seq}")]
+    IncompleteBackup { id: String, seq: u64 },
+
+    #[error("Log segment {0} has not been archived")]
+    NotArchived(u64),
+
+    #[error("Found {problems} problems in the store")]
+    Corrupt { problems: usize },
+
+    #[error("No space left on the device")]
+    DiskFull,
+
+    #[error("Removed {removed} matching keys before failing")]
+    PartiallyRemoved {
+        removed: u64,
+        #[source]
+        source: Box<KvsError>,
+    },
+
+    #[error("Write would take the store past its quota of {quota} bytes ({usage} in use)")]
+    QuotaExceeded { usage: u64, quota: u64 },
+
+    #[error("Keys cannot be empty")]
+    EmptyKey,
+
+    #[error("Key is {size} bytes, over the limit of {max}")]
+    KeyTooLarge { size: u64, max: u64 },
+
+    #[error("Value is {size} bytes, over the limit of {max}")]
+    ValueTooLarge { size: u64, max: u64 },
+
+    #[error("Keyspace name is {size} bytes, over the limit of {max}")]
+    KeyspaceNameTooLarge { size: u64, max: u64 },
+
+    #[error("Invalid backup manifest or archive index")]
+    InvalidManifest(#[source] serde_json::Error),
+
+    #[error("Checksum mismatch in {0}")]
+    ChecksumMismatch(PathBuf),
+
+    #[error("Failed to export")]
+    Export(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Key {key} already exists in keyspace {keyspace}")]
+    Conflict { keyspace: String, key: String },
+
+    #[error("Log segment {0} is not open")]
+    SegmentNotFound(u64),
+
+    #[error("Store is locked by another process{}", locked_by(.pid))]
+    Locked { pid: Option<u32> },
+
+    #[error("No store exists at {0}")]
+    StoreNotFound(PathBuf),
+
+    #[error("A store already exists at {0}")]
+    StoreExists(PathBuf),
+
+    #[error("{0} is not empty and does not hold a store; pass --init to create one there")]
+    NotAStore(PathBuf),
+
+    #[error("No profile named {0} in the config file")]
+    ProfileNotFound(String),
+
+    #[error("Invalid configuration")]
+    Config(#[source] toml::de::Error),
+
+    #[error("Store was opened read-only")]
+    ReadOnly,
+
     #[error("Key not found")]
     KeyNotFound,
 
+    #[error("Keyspace not found")]
+    KeyspaceNotFound,
+
+    #[error("Data directory belongs to the {recorded} engine, not {requested}")]
+    WrongEngine {
+        recorded: String,
+        requested: &'static str,
+    },
+
+    #[error("Operation is not supported by the {0} engine")]
+    Unsupported(&'static str),
+
+    #[error("Sled engine error")]
+    Sled(#[from] sled::Error),
+
+    #[error("Value is not valid UTF-8")]
+    Utf8(#[from] std::string::FromUtf8Error),
+
     #[error("An unexpected I/O error occurred")]
     IoError(#[from] std::io::Error),
 }
+
+impl KvsError {
+    /// The variant's name, as reported in the CLI's JSON errors.
+    pub fn name(&self) -> &'static str {
+        self.describe().0
+    }
+
+    /// The code the `kvs` binary exits with when a command fails with this
+    /// error. Codes 1 and 2 are left for other failures and usage errors.
+    ///
+    /// | Code | Error                  |
+    /// |------|------------------------|
+    /// | 10   | `KeyNotFound`          |
+    /// | 11   | `KeyspaceNotFound`     |
+    /// | 12   | `ReadOnly`             |
+    /// | 13   | `Locked`               |
+    /// | 14   | `StoreNotFound`        |
+    /// | 15   | `StoreExists`          |
+    /// | 16   | `NotAStore`            |
+    /// | 17   | `WrongEngine`          |
+    /// | 18   | `Unsupported`          |
+    /// | 19   | `Config`               |
+    /// | 20   | `ProfileNotFound`      |
+    /// | 21   | `Conflict`             |
+    /// | 22   | `BackupExists`         |
+    /// | 23   | `BackupNotFound`       |
+    /// | 24   | `RestorePointNotFound` |
+    /// | 25   | `QuotaExceeded`        |
+    /// | 26   | `EmptyKey`             |
+    /// | 27   | `KeyTooLarge`          |
+    /// | 28   | `ValueTooLarge`        |
+    /// | 29   | `KeyspaceNameTooLarge` |
+    /// | 30   | `OpenFile`             |
+    /// | 31   | `AppendToLog`          |
+    /// | 32   | `ReadFromLog`          |
+    /// | 33   | `SegmentNotFound`      |
+    /// | 34   | `Import`               |
+    /// | 35   | `Utf8`                 |
+    /// | 36   | `Sled`                 |
+    /// | 37   | `IoError`              |
+    /// | 38   | `Export`               |
+    /// | 39   | `ChecksumMismatch`     |
+    /// | 40   | `InvalidManifest`      |
+    /// | 41   | `IncompleteBackup`     |
+    /// | 42   | `Corrupt`              |
+    /// | 43   | `DiskFull`             |
+    /// | 44   | `NotArchived`          |
+    ///
+    /// `PartiallyRemoved` exits with the code of the error that stopped it.
+    pub fn exit_code(&self) -> u8 {
+        self.describe().1
+    }
+
+    fn describe(&self) -> (&'static str, u8) {
+        match self {
+            Self::KeyNotFound => ("KeyNotFound", 10),
+            Self::KeyspaceNotFound => ("KeyspaceNotFound", 11),
+            Self::ReadOnly => ("ReadOnly", 12),
+            Self::Locked { .. } => ("Locked", 13),
+            Self::StoreNotFound(_) => ("StoreNotFound", 14),
+            Self::StoreExists(_) => ("StoreExists", 15),
+            Self::NotAStore(_) => ("NotAStore", 16),
+            Self::WrongEngine { .. } => ("WrongEngine", 17),
+            Self::Unsupported(_) => ("Unsupported", 18),
+            Self::Config(_) => ("Config", 19),
+            Self::ProfileNotFound(_) => ("ProfileNotFound", 20),
+            Self::Conflict { .. } => ("Conflict", 21),
+            Self::BackupExists(_) => ("BackupExists", 22),
+            Self::BackupNotFound(_) => ("BackupNotFound", 23),
+            Self::RestorePointNotFound(_) => ("RestorePointNotFound", 24),
+            Self::QuotaExceeded { .. } => ("QuotaExceeded", 25),
+            Self::EmptyKey => ("EmptyKey", 26),
+            Self::KeyTooLarge { .. } => ("KeyTooLarge", 27),
+            Self::ValueTooLarge { .. } => ("ValueTooLarge", 28),
+            Self::KeyspaceNameTooLarge { .. } => ("KeyspaceNameTooLarge", 29),
+            Self::OpenFile(_) => ("OpenFile", 30),
+            Self::AppendToLog(_) => ("AppendToLog", 31),
+            Self::ReadFromLog(_) => ("ReadFromLog", 32),
+            Self::SegmentNotFound(_) => ("SegmentNotFound", 33),
+            Self::Import { .. } => ("Import", 34),
+            Self::Utf8(_) => ("Utf8", 35),
+            Self::Sled(_) => ("Sled", 36),
+            Self::IoError(_) => ("IoError", 37),
+            Self::Export(_) => ("Export", 38),
+            Self::ChecksumMismatch(_) => ("ChecksumMismatch", 39),
+            Self::InvalidManifest(_) => ("InvalidManifest", 40),
+            Self::IncompleteBackup { .. } => ("IncompleteBackup", 41),
+            Self::Corrupt { .. } => ("Corrupt", 42),
+            Self::DiskFull => ("DiskFull", 43),
+            Self::NotArchived(_) => ("NotArchived", 44),
+            Self::PartiallyRemoved { source, .. } => ("PartiallyRemoved", source.exit_code()),
+        }
+    }
+
+    /// Replaces an I/O error caused by running out of space with
+    /// `KvsError::DiskFull`, leaving any other error as it is.
+    pub(crate) fn or_disk_full(self) -> Self {
+        let io_error = match &self {
+            Self::IoError(e) | Self::OpenFile(e) => Some(e),
+            Self::AppendToLog(e) => match &**e {
+                bincode::ErrorKind::Io(e) => Some(e),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match io_error.map(std::io::Error::kind) {
+            Some(std::io::ErrorKind::StorageFull) => Self::DiskFull,
+            _ => self,
+        }
+    }
+}
+
+fn locked_by(pid: &Option<u32>) -> String {
+    pid.map(|pid| format!(" (pid {pid})")).unwrap_or_default()
+}
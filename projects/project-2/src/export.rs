@@ -0,0 +1,176 @@
+use crate::{KvsError, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+/// A file format for [`KvStore::export`](crate::KvStore::export) and
+/// [`KvStore::import`](crate::KvStore::import). Every format holds one
+/// record per key, with the key's keyspace, name and value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One JSON object per line.
+    #[value(name = "jsonl")]
+    JsonLines,
+    /// One RON struct per line.
+    Ron,
+    /// Back-to-back BSON documents.
+    Bson,
+    /// Comma-separated values with a `keyspace,key,value` header.
+    Csv,
+}
+
+impl Format {
+    /// Guesses the format from a file's extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+
+        match extension {
+            "jsonl" | "json" => Some(Self::JsonLines),
+            "ron" => Some(Self::Ron),
+            "bson" => Some(Self::Bson),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// What an import does with a key that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Replace the existing value.
+    #[default]
+    Overwrite,
+    /// Stop with `KvsError::Conflict`, before importing anything, if any
+    /// key already exists or appears more than once in the export.
+    Fail,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Record {
+    pub keyspace: String,
+    pub key: String,
+    pub value: String,
+}
+
+/// Writes records one at a time, so exports never hold more than one value
+/// in memory.
+pub(crate) enum RecordWriter<W: Write> {
+    JsonLines(W),
+    Ron(W),
+    Bson(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: Format) -> Self {
+        match format {
+            Format::JsonLines => Self::JsonLines(writer),
+            Format::Ron => Self::Ron(writer),
+            Format::Bson => Self::Bson(writer),
+            Format::Csv => Self::Csv(Box::new(csv::Writer::from_writer(writer))),
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        match self {
+            Self::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, record).map_err(export_error)?;
+                writeln!(writer)?;
+            }
+            Self::Ron(writer) => {
+                ron::ser::to_writer(&mut *writer, record).map_err(export_error)?;
+                writeln!(writer)?;
+            }
+            Self::Bson(writer) => {
+                let document = bson::to_document(record).map_err(export_error)?;
+                document.to_writer(writer).map_err(export_error)?;
+            }
+            Self::Csv(writer) => writer.serialize(record).map_err(export_error)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Self::JsonLines(mut writer) | Self::Ron(mut writer) | Self::Bson(mut writer) => {
+                writer.flush()?
+            }
+            Self::Csv(mut writer) => writer.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads records lazily. Errors carry the 1-based number of the record that
+/// could not be read; for line-based formats, that is its line number.
+pub(crate) fn read_records<'a>(
+    mut reader: impl BufRead + 'a,
+    format: Format,
+) -> Box<dyn Iterator<Item = Result<Record>> + 'a> {
+    match format {
+        Format::JsonLines => read_lines(reader, |line| {
+            serde_json::from_str(line).map_err(|e| e.into())
+        }),
+        Format::Ron => read_lines(reader, |line| ron::from_str(line).map_err(|e| e.into())),
+        Format::Bson => {
+            let mut number = 0;
+
+            Box::new(std::iter::from_fn(move || {
+                match reader.fill_buf() {
+                    Ok([]) => return None,
+                    Ok(_) => {}
+                    Err(e) => return Some(Err(e.into())),
+                }
+
+                number += 1;
+                Some(bson::from_reader(&mut reader).map_err(|e| import_error(number, e)))
+            }))
+        }
+        Format::Csv => {
+            let reader = csv::Reader::from_reader(reader);
+
+            Box::new(
+                (1..)
+                    .zip(reader.into_deserialize())
+                    .map(|(number, record)| record.map_err(|e| import_error(number, e))),
+            )
+        }
+    }
+}
+
+type ParseResult = std::result::Result<Record, Box<dyn Error + Send + Sync>>;
+
+fn read_lines<'a>(
+    reader: impl BufRead + 'a,
+    parse: impl Fn(&str) -> ParseResult + 'a,
+) -> Box<dyn Iterator<Item = Result<Record>> + 'a> {
+    Box::new(
+        (1..)
+            .zip(reader.lines())
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(move |(number, line)| {
+                let line = line?;
+                parse(&line).map_err(|source| KvsError::Import {
+                    record: number,
+                    source,
+                })
+            }),
+    )
+}
+
+fn import_error(record: u64, source: impl Error + Send + Sync + 'static) -> KvsError {
+    KvsError::Import {
+        record,
+        source: Box::new(source),
+    }
+}
+
+fn export_error(source: impl Error + Send + Sync + 'static) -> KvsError {
+    KvsError::Export(Box::new(source))
+}
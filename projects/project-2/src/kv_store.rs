@@ -1,58 +1,676 @@
 use crate::{
-    log::{Log, LogCommand, LogPointer},
-    KvsError, Result,
+    archive::{restore_archive, RestorePoint},
+    backup::{
+        find_sibling_backup, finish_backup, freeze_segments, prepare_backup_dir, restore,
+        split_incremental, BackupManifest,
+    },
+    cache::{CacheStats, ValueCache},
+    engines::{Engine, KvsEngine},
+    export::{read_records, Record, RecordWriter},
+    log::{Index, Keyspaces, Log, LogCommand, LogPointer, LogReader, Lsn, DEFAULT_KEYSPACE},
+    repair::{repair, RepairReport},
+    utils::{index_drop, index_remove, index_set, replace_index},
+    verify::{check_pointers, verify, VerifyReport},
+    Format, KvStoreOptions, KvsError, OnConflict, Problem, Result, VfsFile,
+};
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufWriter, Seek, SeekFrom, Write},
+    ops::Bound,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
 };
-use std::{collections::BTreeMap, io::Write, path::Path};
-
-const UNCOMPACTED_BYTES_THRESHOLD: u64 = 1_024 * 1_024;
 
-#[derive(Debug)]
+/// A log-structured key/value store. `KvStore` is cheap to clone, and clones
+/// share the same underlying store, so it can be handed to as many threads
+/// as needed. Reads run concurrently; writes are serialized.
+#[derive(Debug, Clone)]
 pub struct KvStore {
-    log: Log,
-    index: BTreeMap<String, LogPointer>,
-    uncompacted_bytes: u64,
+    keyspaces: Arc<Keyspaces>,
+    reader: LogReader,
+    cache: Option<Arc<ValueCache>>,
+    writer: Arc<Mutex<KvStoreWriter>>,
+    read_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub keyspaces: u64,
+    pub keys: u64,
+    pub uncompacted_bytes: u64,
+    pub disk_bytes: u64,
+    pub disk_quota: Option<u64>,
+    /// Open segments read through a memory map rather than `read_at`.
+    pub mapped_segments: u64,
+    pub cache: Option<CacheStats>,
 }
 
 impl KvStore {
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let path = path.as_ref();
-        let (uncompacted_bytes, index, log) = Log::init(path)?;
+        Self::options().open(path)
+    }
 
-        Ok(Self {
+    /// Returns a builder for opening a store with non-default settings.
+    pub fn options() -> KvStoreOptions {
+        KvStoreOptions::new()
+    }
+
+    /// Opens an existing store without ever writing to it. Any number of
+    /// read-only handles can share a store with the process that owns it;
+    /// call [`KvStore::refresh`] to pick up that process's latest writes.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        Self::options().read_only(true).open(path)
+    }
+
+    pub(crate) fn open_with_options(path: &Path, options: &KvStoreOptions) -> Result<Self> {
+        let vfs = &*options.vfs;
+        let exists = Engine::recorded_in(vfs, path)?.is_some();
+        // A read-only handle never writes, so an empty directory reads as an empty store.
+        let missing = match options.read_only {
+            true => !vfs.is_dir(path),
+            false => !exists && !options.create_if_missing,
+        };
+        if missing {
+            return Err(KvsError::StoreNotFound(path.to_owned()));
+        }
+        if exists && options.error_if_exists {
+            return Err(KvsError::StoreExists(path.to_owned()));
+        }
+
+        if options.read_only {
+            Engine::Kvs.check_dir_in(vfs, path)?;
+        } else {
+            vfs.create_dir_all(path)?;
+            Engine::Kvs.claim_dir_in(vfs, path)?;
+        }
+
+        let (uncompacted_bytes, keyspaces, log) = Log::init(path, options)?;
+        let keyspaces = Arc::new(keyspaces);
+        let reader = log.reader();
+        let cache = (options.cache_size > 0).then(|| Arc::new(ValueCache::new(options.cache_size)));
+
+        let (max_key_size, max_value_size) = options.size_limits();
+        let writer = KvStoreWriter {
+            disk_bytes: reader.disk_size()?,
+            disk_quota: options.max_disk_size,
+            max_key_size,
+            max_value_size,
             log,
-            index,
+            keyspaces: Arc::clone(&keyspaces),
+            cache: cache.clone(),
             uncompacted_bytes,
+            auto_compact: options.auto_compact,
+            compaction_threshold: options.compaction_threshold,
+        };
+
+        Ok(Self {
+            keyspaces,
+            reader,
+            cache,
+            writer: Arc::new(Mutex::new(writer)),
+            read_only: options.read_only,
         })
     }
 
-    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
-        let log_command = LogCommand::Set(key.to_owned(), value.to_owned());
-        let pointer = self.log.append(log_command)?;
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.set_in(DEFAULT_KEYSPACE, key, value)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        self.get_in(DEFAULT_KEYSPACE, key)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        self.remove_in(DEFAULT_KEYSPACE, key)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.keys_in(DEFAULT_KEYSPACE, "")
+    }
 
-        if let Some(prev_pointer) = self.index.insert(key.to_owned(), pointer) {
-            self.add_uncompacted_bytes(prev_pointer.length)?;
+    /// Lists the keys that start with `prefix`, in sorted order.
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.keys_in(DEFAULT_KEYSPACE, prefix)
+    }
+
+    /// Lists the keys that match `regex`, in sorted order. Only the index is
+    /// searched, so no values are read from disk.
+    pub fn keys_matching(&self, regex: &Regex) -> Vec<String> {
+        self.keys_matching_in(DEFAULT_KEYSPACE, regex)
+    }
+
+    /// Removes every key that matches `regex`, holding the write lock so no
+    /// other write can land between the removals. Returns the number of keys
+    /// removed. Each key is removed by its own record, so this is not atomic:
+    /// if a removal fails, the keys before it stay removed and
+    /// `KvsError::PartiallyRemoved` says how many there were.
+    pub fn remove_matching(&self, regex: &Regex) -> Result<u64> {
+        self.writer()?.remove_matching(DEFAULT_KEYSPACE, regex)
+    }
+
+    /// Reads every key that starts with `prefix` along with its value.
+    pub fn scan(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        self.scan_in(DEFAULT_KEYSPACE, prefix)
+    }
+
+    /// Checks the index for a key without reading its value from disk.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.pointer(DEFAULT_KEYSPACE, key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len_in(DEFAULT_KEYSPACE)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a handle to a named keyspace. Keyspaces share the store's log
+    /// and compaction, but keep their keys separate from one another.
+    pub fn keyspace(&self, name: &str) -> Keyspace {
+        Keyspace {
+            store: self.clone(),
+            name: name.to_owned(),
         }
+    }
 
-        Ok(())
+    /// Lists the keyspaces that currently hold at least one key.
+    pub fn keyspaces(&self) -> Vec<String> {
+        self.keyspaces
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Removes every key in a keyspace by appending a single record to the log.
+    pub fn drop_keyspace(&self, name: &str) -> Result<()> {
+        self.writer()?.drop_keyspace(name)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Re-reads the log from disk so a read-only store sees writes made by
+    /// the process that owns it. Writable stores are always up to date, so
+    /// this does nothing for them.
+    pub fn refresh(&self) -> Result<()> {
+        self.writer.lock().unwrap().refresh()
     }
 
-    pub fn get(&mut self, key: &str) -> Result<Option<String>> {
-        match self.index.get(key) {
-            Some(pointer) => self.log.get_value(pointer),
-            None => Ok(None),
+    /// Rewrites the log so that it only holds live records. Stores compact
+    /// on their own unless opened with `auto_compact(false)`.
+    pub fn compact(&self) -> Result<()> {
+        self.writer()?.compact()
+    }
+
+    pub fn stats(&self) -> Stats {
+        let (uncompacted_bytes, disk_bytes, disk_quota) = {
+            let writer = self.writer.lock().unwrap();
+            (
+                writer.uncompacted_bytes,
+                writer.disk_bytes,
+                writer.disk_quota,
+            )
+        };
+        let keys = self
+            .keyspaces
+            .iter()
+            .map(|index| index.value().len() as u64)
+            .sum();
+
+        Stats {
+            keyspaces: self.keyspaces.len() as u64,
+            keys,
+            uncompacted_bytes,
+            disk_bytes,
+            disk_quota,
+            mapped_segments: self.reader.mapped_segments(),
+            cache: self.cache.as_ref().map(|cache| cache.stats()),
         }
     }
 
-    pub fn remove(&mut self, key: &str) -> Result<()> {
-        if !self.index.contains_key(key) {
+    /// The number of bytes the store's segments take up on disk.
+    pub fn disk_size(&self) -> Result<u64> {
+        self.reader.disk_size()
+    }
+
+    /// Writes every key in every keyspace to `writer`, returning the number
+    /// of keys written. Values are read and written one at a time.
+    pub fn export(&self, writer: impl Write, format: Format) -> Result<u64> {
+        let mut writer = RecordWriter::new(writer, format);
+        let mut count = 0;
+
+        for keyspace in self.keyspaces.iter() {
+            for entry in keyspace.value().iter() {
+                // Skip keys that were removed since the index was walked.
+                let Some(value) = self.get_in(keyspace.key(), entry.key())? else {
+                    continue;
+                };
+
+                let record = Record {
+                    keyspace: keyspace.key().clone(),
+                    key: entry.key().clone(),
+                    value,
+                };
+                writer.write(&record)?;
+                count += 1;
+            }
+        }
+
+        writer.finish()?;
+        Ok(count)
+    }
+
+    /// Sets every key read from an export, returning the number of keys
+    /// imported. With `OnConflict::Fail` the export is read twice: once to
+    /// check every record, holding only the keys, and once to apply them, so
+    /// a conflict or a malformed record leaves the store untouched.
+    pub fn import<R: BufRead + Seek>(
+        &self,
+        mut reader: R,
+        format: Format,
+        on_conflict: OnConflict,
+    ) -> Result<u64> {
+        if on_conflict == OnConflict::Overwrite {
+            return self.import_stream(reader, format);
+        }
+
+        let start = reader.stream_position()?;
+        let mut writer = self.writer()?;
+
+        let mut seen = HashSet::new();
+        for record in read_records(&mut reader, format) {
+            let Record {
+                keyspace,
+                key,
+                value,
+            } = record?;
+            writer.check_sizes(&keyspace, &key, &value)?;
+
+            // A key imported twice would overwrite itself just the same.
+            let duplicate = !seen.insert((keyspace.clone(), key.clone()));
+            if duplicate || writer.contains_key(&keyspace, &key) {
+                return Err(KvsError::Conflict { keyspace, key });
+            }
+        }
+        drop(seen);
+
+        reader.seek(SeekFrom::Start(start))?;
+        let mut count = 0;
+        for record in read_records(reader, format) {
+            let record = record?;
+            writer.set(&record.keyspace, &record.key, &record.value)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Sets every key read from an export as it is read, overwriting keys
+    /// that exist. For input that cannot be read twice, such as a pipe.
+    pub fn import_stream(&self, reader: impl BufRead, format: Format) -> Result<u64> {
+        let mut count = 0;
+
+        for record in read_records(reader, format) {
+            let record = record?;
+            self.writer()?
+                .set(&record.keyspace, &record.key, &record.value)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Takes a consistent backup of every keyspace into `dir`, which must be
+    /// missing or empty. Writers only wait while the active segment is sealed
+    /// and the sealed segments are linked into the backup; copying and
+    /// checksumming happen while writes carry on.
+    pub fn backup_to(&self, dir: impl AsRef<Path>) -> Result<BackupManifest> {
+        let dir = dir.as_ref();
+        prepare_backup_dir(dir)?;
+
+        let (lsn, frozen) = {
+            let mut writer = self.writer()?;
+            let (lsn, seqs) = writer.log.freeze()?;
+            let frozen = freeze_segments(writer.log.path(), &seqs, dir)?;
+            (lsn, frozen)
+        };
+
+        finish_backup(dir, lsn, frozen, None, Vec::new())
+    }
+
+    /// Takes a backup into `dir` holding only the segments written since the
+    /// backup `since`, which must sit in a directory next to `dir`. Restoring
+    /// it pulls the remaining segments from its ancestors.
+    pub fn incremental_backup_to(
+        &self,
+        dir: impl AsRef<Path>,
+        since: &str,
+    ) -> Result<BackupManifest> {
+        let dir = dir.as_ref();
+        let (_, parent) = find_sibling_backup(dir, since)?;
+        prepare_backup_dir(dir)?;
+
+        let (lsn, frozen, inherited) = {
+            let mut writer = self.writer()?;
+            let (lsn, seqs) = writer.log.freeze()?;
+            let (new, inherited) = split_incremental(&parent, &seqs)?;
+            let frozen = freeze_segments(writer.log.path(), &new, dir)?;
+            (lsn, frozen, inherited)
+        };
+
+        finish_backup(dir, lsn, frozen, Some(parent.id), inherited)
+    }
+
+    /// Replaces the store at `path` with a backup taken by
+    /// [`KvStore::backup_to`] or [`KvStore::incremental_backup_to`], after
+    /// verifying its checksums. Fails with
+    /// `KvsError::Locked` if the store is open anywhere.
+    pub fn restore_from(
+        backup: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+    ) -> Result<BackupManifest> {
+        restore(backup.as_ref(), path.as_ref())
+    }
+
+    /// Checks the store at `path` for corrupt records, misnamed segments and
+    /// files it did not write. The store must not be open anywhere.
+    pub fn verify(path: impl AsRef<Path>) -> Result<VerifyReport> {
+        verify(path.as_ref())
+    }
+
+    /// Checks that every entry in this store's index points at a set record
+    /// for its key in the segments on disk, reporting each one that does not
+    /// as a `Problem::BadPointer`. [`KvStore::verify`] rebuilds the index
+    /// from the segments, so it cannot catch one that has drifted from them
+    /// while open. Fails with `KvsError::ReadOnly` on a read-only store.
+    pub fn check_index(&self) -> Result<Vec<Problem>> {
+        let writer = self.writer()?;
+        check_pointers(&self.keyspaces, writer.log.open_readers()?)
+    }
+
+    /// Salvages what it can from a store that [`KvStore::verify`] finds
+    /// problems in, moving damaged and unknown files to `lost+found`. The
+    /// store must not be open anywhere.
+    pub fn repair(path: impl AsRef<Path>) -> Result<RepairReport> {
+        repair(path.as_ref())
+    }
+
+    /// Builds a fresh store at `path` by replaying the segments archived in
+    /// `archive` (see [`KvStoreOptions::archive_dir`]) up to `until`.
+    /// Returns the LSN the store was restored to.
+    pub fn restore_archive(
+        archive: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+        until: RestorePoint,
+    ) -> Result<Lsn> {
+        restore_archive(archive.as_ref(), path.as_ref(), until)
+    }
+
+    /// Exports the store as JSON lines.
+    pub fn dump(&self, writer: impl Write) -> Result<u64> {
+        self.export(writer, Format::JsonLines)
+    }
+
+    /// Imports JSON lines written by [`KvStore::dump`], overwriting existing keys.
+    pub fn load(&self, reader: impl BufRead) -> Result<u64> {
+        self.import_stream(reader, Format::JsonLines)
+    }
+
+    fn set_in(&self, keyspace: &str, key: &str, value: &str) -> Result<()> {
+        self.writer()?.set(keyspace, key, value)
+    }
+
+    fn get_in(&self, keyspace: &str, key: &str) -> Result<Option<String>> {
+        loop {
+            let Some(pointer) = self.pointer(keyspace, key) else {
+                return Ok(None);
+            };
+
+            if let Some(value) = self
+                .cache
+                .as_ref()
+                .and_then(|c| c.get(keyspace, key, &pointer))
+            {
+                return Ok(Some(value));
+            }
+
+            match self.reader.get_value(&pointer) {
+                Ok(Some(value)) => {
+                    if let Some(cache) = &self.cache {
+                        cache.insert(keyspace, key, pointer, value.clone());
+                    }
+                    return Ok(Some(value));
+                }
+                // Compaction moved the record and closed its old segment
+                // between the index lookup and the read, so look it up again.
+                Err(KvsError::SegmentNotFound(_))
+                    if self.pointer(keyspace, key).as_ref() != Some(&pointer) =>
+                {
+                    continue
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn keys_in(&self, keyspace: &str, prefix: &str) -> Vec<String> {
+        let Some(index) = self.keyspaces.get(keyspace) else {
+            return Vec::new();
+        };
+
+        let keys = index
+            .value()
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|entry| entry.key().clone())
+            .take_while(|key| key.starts_with(prefix))
+            .collect();
+        keys
+    }
+
+    fn keys_matching_in(&self, keyspace: &str, regex: &Regex) -> Vec<String> {
+        self.keyspaces
+            .get(keyspace)
+            .map(|index| matching_keys(index.value(), regex))
+            .unwrap_or_default()
+    }
+
+    fn scan_in(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+
+        for key in self.keys_in(keyspace, prefix) {
+            // Skip keys that were removed since the index was walked.
+            if let Some(value) = self.get_in(keyspace, &key)? {
+                pairs.push((key, value));
+            }
+        }
+
+        Ok(pairs)
+    }
+
+    fn len_in(&self, keyspace: &str) -> usize {
+        self.keyspaces
+            .get(keyspace)
+            .map_or(0, |index| index.value().len())
+    }
+
+    fn remove_in(&self, keyspace: &str, key: &str) -> Result<()> {
+        self.writer()?.remove(keyspace, key)
+    }
+
+    fn writer(&self) -> Result<MutexGuard<'_, KvStoreWriter>> {
+        if self.read_only {
+            return Err(KvsError::ReadOnly);
+        }
+
+        Ok(self.writer.lock().unwrap())
+    }
+
+    fn pointer(&self, keyspace: &str, key: &str) -> Option<LogPointer> {
+        let index = self.keyspaces.get(keyspace)?;
+        let pointer = index.value().get(key)?;
+        let pointer = pointer.value().read().unwrap().clone();
+        Some(pointer)
+    }
+}
+
+/// The single writer behind every clone of a [`KvStore`]. Holding its lock
+/// serializes appends and compaction, while readers go straight to the index.
+#[derive(Debug)]
+struct KvStoreWriter {
+    log: Log,
+    keyspaces: Arc<Keyspaces>,
+    cache: Option<Arc<ValueCache>>,
+    uncompacted_bytes: u64,
+    /// The size of the open segments, kept up to date as records are
+    /// appended so the quota can be checked without touching the disk.
+    disk_bytes: u64,
+    disk_quota: Option<u64>,
+    max_key_size: u64,
+    max_value_size: u64,
+    auto_compact: bool,
+    compaction_threshold: u64,
+}
+
+impl KvStoreWriter {
+    fn set(&mut self, keyspace: &str, key: &str, value: &str) -> Result<()> {
+        self.check_sizes(keyspace, key, value)?;
+        let log_command = LogCommand::set(keyspace, key, value);
+        self.make_room_for(&log_command)?;
+        let pointer = self.log.append(log_command)?;
+        self.disk_bytes += pointer.length;
+
+        let replaced_bytes = index_set(&self.keyspaces, keyspace, key.to_owned(), pointer);
+        if let Some(cache) = &self.cache {
+            cache.invalidate(keyspace, key);
+        }
+
+        self.add_uncompacted_bytes(replaced_bytes)
+    }
+
+    fn contains_key(&self, keyspace: &str, key: &str) -> bool {
+        self.keyspaces
+            .get(keyspace)
+            .is_some_and(|index| index.value().contains_key(key))
+    }
+
+    fn remove(&mut self, keyspace: &str, key: &str) -> Result<()> {
+        if !self.contains_key(keyspace, key) {
             return Err(KvsError::KeyNotFound);
         }
 
-        let log_command = LogCommand::Remove(key.to_owned());
-        let _ = self.log.append(log_command)?;
+        let log_command = LogCommand::remove(keyspace, key);
+        let pointer = self.log.append(log_command)?;
+        self.disk_bytes += pointer.length;
+
+        let removed_bytes = index_remove(&self.keyspaces, keyspace, key);
+        if let Some(cache) = &self.cache {
+            cache.invalidate(keyspace, key);
+        }
+
+        self.add_uncompacted_bytes(removed_bytes)
+    }
+
+    fn remove_matching(&mut self, keyspace: &str, regex: &Regex) -> Result<u64> {
+        let keys = self
+            .keyspaces
+            .get(keyspace)
+            .map(|index| matching_keys(index.value(), regex))
+            .unwrap_or_default();
+
+        for (removed, key) in keys.iter().enumerate() {
+            if let Err(e) = self.remove(keyspace, key) {
+                return Err(KvsError::PartiallyRemoved {
+                    removed: removed as u64,
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        Ok(keys.len() as u64)
+    }
+
+    fn drop_keyspace(&mut self, name: &str) -> Result<()> {
+        if !self.keyspaces.contains_key(name) {
+            return Err(KvsError::KeyspaceNotFound);
+        }
+
+        let log_command = LogCommand::DropKeyspace(name.to_owned());
+        let pointer = self.log.append(log_command)?;
+        self.disk_bytes += pointer.length;
+
+        let dropped_bytes = index_drop(&self.keyspaces, name);
+        if let Some(cache) = &self.cache {
+            cache.invalidate_keyspace(name);
+        }
+
+        self.add_uncompacted_bytes(dropped_bytes)
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        if !self.log.is_read_only() {
+            return Ok(());
+        }
+
+        let (uncompacted_bytes, keyspaces) = self.log.refresh()?;
+        replace_index(&self.keyspaces, keyspaces);
+        self.log.close_removed_segments()?;
+        self.uncompacted_bytes = uncompacted_bytes;
+        self.disk_bytes = self.log.reader().disk_size()?;
+
+        Ok(())
+    }
+
+    /// Keyspace names share the key size limit, which keeps every record
+    /// within what the log can read back.
+    fn check_sizes(&self, keyspace: &str, key: &str, value: &str) -> Result<()> {
+        let (key_size, value_size) = (key.len() as u64, value.len() as u64);
+        let keyspace_size = keyspace.len() as u64;
+
+        if key.is_empty() {
+            return Err(KvsError::EmptyKey);
+        }
+        if key_size > self.max_key_size {
+            return Err(KvsError::KeyTooLarge {
+                size: key_size,
+                max: self.max_key_size,
+            });
+        }
+        if value_size > self.max_value_size {
+            return Err(KvsError::ValueTooLarge {
+                size: value_size,
+                max: self.max_value_size,
+            });
+        }
+        if keyspace_size > self.max_key_size {
+            return Err(KvsError::KeyspaceNameTooLarge {
+                size: keyspace_size,
+                max: self.max_key_size,
+            });
+        }
+
+        Ok(())
+    }
 
-        if let Some(prev_pointer) = self.index.remove(key) {
-            self.add_uncompacted_bytes(prev_pointer.length)?;
+    /// Checks that `log_command` fits within the disk quota, compacting
+    /// first if it does not and there is anything to reclaim.
+    fn make_room_for(&mut self, log_command: &LogCommand) -> Result<()> {
+        let Some(quota) = self.disk_quota else {
+            return Ok(());
+        };
+        let length = bincode::serialized_size(log_command).map_err(KvsError::AppendToLog)?;
+
+        if self.disk_bytes + length > quota && self.uncompacted_bytes > 0 {
+            self.compact()?;
+        }
+        if self.disk_bytes + length > quota {
+            return Err(KvsError::QuotaExceeded {
+                usage: self.disk_bytes,
+                quota,
+            });
         }
 
         Ok(())
@@ -61,7 +679,7 @@ impl KvStore {
     fn add_uncompacted_bytes(&mut self, bytes_len: u64) -> Result<()> {
         self.uncompacted_bytes += bytes_len;
 
-        if self.uncompacted_bytes > UNCOMPACTED_BYTES_THRESHOLD {
+        if self.auto_compact && self.uncompacted_bytes > self.compaction_threshold {
             self.compact()?;
         }
 
@@ -69,19 +687,159 @@ impl KvStore {
     }
 
     fn compact(&mut self) -> Result<()> {
-        let (commit_seq, mut commit_file) = self.log.prepare_commit()?;
-        let mut offset = 0;
+        let (commit_seq, commit_file) =
+            self.log.prepare_commit().map_err(KvsError::or_disk_full)?;
+        let moved = match self.write_commit(commit_seq, commit_file) {
+            Ok(moved) => moved,
+            Err(e) => {
+                // The index still points at the old segments, so nothing is lost.
+                let _ = self.log.abort_commit(commit_seq);
+                return Err(e.or_disk_full());
+            }
+        };
+
+        for (keyspace, key, old_pointer, pointer) in moved {
+            let Some(index) = self.keyspaces.get(&keyspace) else {
+                continue;
+            };
+
+            if let Some(cache) = &self.cache {
+                cache.repoint(&keyspace, &key, &old_pointer, &pointer);
+            }
 
-        for pointer in self.index.values_mut() {
-            let bytes_written = self.log.stage_to_commit_file(&mut commit_file, pointer)?;
-            pointer.update(commit_seq, offset, bytes_written);
-            offset += bytes_written;
+            if let Some(entry) = index.value().get(&key) {
+                *entry.value().write().unwrap() = pointer;
+            };
         }
 
-        commit_file.flush()?;
         self.log.remove_stale_logs(commit_seq)?;
         self.uncompacted_bytes = 0;
+        self.disk_bytes = self.log.reader().disk_size()?;
 
         Ok(())
     }
+
+    /// Copies every live record into the commit file and seals it, returning
+    /// each key's old and new position.
+    fn write_commit(
+        &mut self,
+        commit_seq: u64,
+        mut commit_file: BufWriter<Box<dyn VfsFile>>,
+    ) -> Result<Vec<(String, String, LogPointer, LogPointer)>> {
+        let mut offset = 0;
+        let mut moved = Vec::new();
+
+        for keyspace in self.keyspaces.iter() {
+            for entry in keyspace.value().iter() {
+                let old_pointer = entry.value().read().unwrap().clone();
+                let bytes_written = self
+                    .log
+                    .stage_to_commit_file(&mut commit_file, &old_pointer)?;
+                let pointer = LogPointer::new(commit_seq, offset, bytes_written);
+                offset += bytes_written;
+
+                moved.push((
+                    keyspace.key().clone(),
+                    entry.key().clone(),
+                    old_pointer,
+                    pointer,
+                ));
+            }
+        }
+
+        // Readers may follow the new pointers as soon as they are published,
+        // so the commit file has to be complete first.
+        self.log.seal_commit_file(commit_seq, commit_file)?;
+
+        Ok(moved)
+    }
+}
+
+fn matching_keys(index: &Index, regex: &Regex) -> Vec<String> {
+    index
+        .iter()
+        .map(|entry| entry.key().clone())
+        .filter(|key| regex.is_match(key))
+        .collect()
+}
+
+/// A named keyspace within a [`KvStore`], obtained from [`KvStore::keyspace`].
+#[derive(Debug, Clone)]
+pub struct Keyspace {
+    store: KvStore,
+    name: String,
+}
+
+impl Keyspace {
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.store.set_in(&self.name, key, value)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        self.store.get_in(&self.name, key)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        self.store.remove_in(&self.name, key)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.store.keys_in(&self.name, "")
+    }
+
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.store.keys_in(&self.name, prefix)
+    }
+
+    pub fn keys_matching(&self, regex: &Regex) -> Vec<String> {
+        self.store.keys_matching_in(&self.name, regex)
+    }
+
+    pub fn remove_matching(&self, regex: &Regex) -> Result<u64> {
+        self.store.writer()?.remove_matching(&self.name, regex)
+    }
+
+    pub fn scan(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        self.store.scan_in(&self.name, prefix)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.store.pointer(&self.name, key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len_in(&self.name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl KvsEngine for KvStore {
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        KvStore::set(self, key, value)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        KvStore::get(self, key)
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        KvStore::remove(self, key)
+    }
+}
+
+impl KvsEngine for Keyspace {
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        Keyspace::set(self, key, value)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Keyspace::get(self, key)
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        Keyspace::remove(self, key)
+    }
 }
@@ -1,10 +1,30 @@
+pub mod engines;
 pub mod log;
 pub mod utils;
 
+mod archive;
+mod backup;
+mod cache;
 mod cli;
+mod config;
 mod errors;
+mod export;
 mod kv_store;
+mod options;
+mod repair;
+mod verify;
+mod vfs;
 
+pub use archive::{ArchivedSegment, RestorePoint};
+pub use backup::{BackupManifest, BackupSegment};
+pub use cache::CacheStats;
 pub use cli::*;
+pub use config::*;
+pub use engines::{Engine, KvsEngine};
 pub use errors::*;
+pub use export::{Format, OnConflict};
 pub use kv_store::*;
+pub use options::*;
+pub use repair::RepairReport;
+pub use verify::{Problem, VerifyReport};
+pub use vfs::{Fault, FaultyVfs, MemoryVfs, OsVfs, Vfs, VfsFile};
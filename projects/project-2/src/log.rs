@@ -1,23 +1,159 @@
+use crate::archive::Archive;
 use crate::utils::*;
-use crate::{KvsError, Result};
+use crate::{KvStoreOptions, KvsError, Result, SyncPolicy, Vfs, VfsFile};
+use bincode::Options;
+use crossbeam_skiplist::SkipMap;
+use memmap2::Mmap;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::fs;
-use std::io::{Read, Seek, Write};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, BufRead, Seek, SeekFrom, Write};
 use std::path::PathBuf;
-use std::{
-    fs::File,
-    io::{BufReader, BufWriter, SeekFrom},
-    path::Path,
-};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::{fs::File, io::BufWriter, path::Path};
+
+pub const DEFAULT_KEYSPACE: &str = "default";
+const REFRESH_ATTEMPTS: u32 = 3;
+
+/// The largest record the log will decode. A longer one can only come from a
+/// corrupt length prefix, and is refused before anything is allocated for it.
+pub const MAX_RECORD_SIZE: u64 = 1 << 30;
+/// The most the key and value size limits can be raised to, leaving room for
+/// both in a record.
+pub const MAX_FIELD_SIZE: u64 = MAX_RECORD_SIZE / 4;
+
+/// Index values sit behind their own lock so that a pointer can be moved in
+/// place. Replacing a `SkipMap` entry briefly unlinks it, which would let
+/// concurrent readers miss a key that exists.
+pub type Index = SkipMap<String, RwLock<LogPointer>>;
+pub type Keyspaces = SkipMap<String, Index>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum LogCommand {
     Set(String, String),
     Remove(String),
+    KeyspaceSet(String, String, String),
+    KeyspaceRemove(String, String),
+    DropKeyspace(String),
 }
 
+impl LogCommand {
+    pub fn set(keyspace: &str, key: &str, value: &str) -> Self {
+        match keyspace {
+            DEFAULT_KEYSPACE => Self::Set(key.to_owned(), value.to_owned()),
+            _ => Self::KeyspaceSet(keyspace.to_owned(), key.to_owned(), value.to_owned()),
+        }
+    }
+
+    pub fn remove(keyspace: &str, key: &str) -> Self {
+        match keyspace {
+            DEFAULT_KEYSPACE => Self::Remove(key.to_owned()),
+            _ => Self::KeyspaceRemove(keyspace.to_owned(), key.to_owned()),
+        }
+    }
+}
+
+/// A record decoded from a segment, along with where it sits in it.
 #[derive(Debug)]
+pub struct LogRecord {
+    pub offset: u64,
+    pub length: u64,
+    pub command: LogCommand,
+}
+
+/// Decodes the records of a segment in order. A record that cannot be
+/// decoded, such as one torn by a crash, ends the iteration with an error.
+pub struct LogRecords<R> {
+    reader: R,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: BufRead + Seek> LogRecords<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        reader.seek(SeekFrom::Start(0))?;
+
+        Ok(Self {
+            reader,
+            offset: 0,
+            done: false,
+        })
+    }
+
+    fn next_record(&mut self) -> Result<Option<LogRecord>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+
+        let command = record_options()
+            .deserialize_from(&mut self.reader)
+            .map_err(KvsError::ReadFromLog)?;
+        let position = self.reader.stream_position()?;
+        let record = LogRecord {
+            offset: self.offset,
+            length: position - self.offset,
+            command,
+        };
+        self.offset = position;
+
+        Ok(Some(record))
+    }
+}
+
+impl<R: BufRead + Seek> Iterator for LogRecords<R> {
+    type Item = Result<LogRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.next_record().transpose();
+        self.done = !matches!(record, Some(Ok(_)));
+        record
+    }
+}
+
+/// The encoding `bincode::serialize` writes records in, with reads capped
+/// at [`MAX_RECORD_SIZE`].
+fn record_options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_RECORD_SIZE)
+}
+
+/// A log sequence number: a position in the log, given by a segment and a
+/// byte offset into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Lsn {
+    pub seq: u64,
+    pub offset: u64,
+}
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.seq, self.offset)
+    }
+}
+
+impl FromStr for Lsn {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("expected an LSN as SEQ:OFFSET, got {s:?}");
+        let (seq, offset) = s.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Self {
+            seq: seq.parse().map_err(|_| invalid())?,
+            offset: offset.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LogPointer {
     pub file_id: u64,
     pub offset: u64,
@@ -38,107 +174,757 @@ impl LogPointer {
     }
 }
 
+/// An open segment file. Sealed segments never change, so when memory
+/// mapping is enabled they are read straight out of a mapping instead of
+/// through `read_at`. Only files on the OS filesystem can be mapped.
+#[derive(Debug, Clone)]
+enum Segment {
+    File(Arc<dyn VfsFile>),
+    Mapped(Arc<Mmap>),
+}
+
+impl Segment {
+    fn map(file: Box<dyn VfsFile>) -> Result<Self> {
+        let Some(os_file) = file.as_os_file() else {
+            return Ok(Self::File(Arc::from(file)));
+        };
+
+        // SAFETY: only sealed segments are mapped, and a sealed segment is
+        // never written to, truncated or rewritten in place again: torn tails
+        // are only cut off the active segment, and compaction, restores and
+        // repairs write new files. Compaction does unlink segments that clones
+        // of this map may still be reading, which is fine, as the pages stay
+        // valid until the last map is dropped.
+        let mmap = unsafe { Mmap::map(os_file) }.map_err(KvsError::OpenFile)?;
+        Ok(Self::Mapped(Arc::new(mmap)))
+    }
+
+    fn len(&self) -> Result<u64> {
+        match self {
+            Self::File(file) => Ok(file.size()?),
+            Self::Mapped(mmap) => Ok(mmap.len() as u64),
+        }
+    }
+
+    fn with_bytes<T>(&self, pointer: &LogPointer, f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
+        match self {
+            Self::File(file) => {
+                let mut buffer = vec![0; pointer.length as usize];
+                file.read_exact_at(&mut buffer, pointer.offset)?;
+                f(&buffer)
+            }
+            Self::Mapped(mmap) => {
+                let start = pointer.offset as usize;
+                let end = start + pointer.length as usize;
+                let bytes = mmap
+                    .get(start..end)
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+                f(bytes)
+            }
+        }
+    }
+}
+
+/// The segments a [`LogReader`] can read from. Segments still being written
+/// stay open, while sealed ones are reopened whenever they are needed and,
+/// with a limit set, only the most recently used are kept open.
+#[derive(Debug)]
+struct Segments {
+    active: BTreeMap<u64, Segment>,
+    /// Every sealed segment, open or not.
+    sealed: BTreeMap<u64, SealedSegment>,
+    max_open: Option<usize>,
+    /// Only advanced under the write lock, when a segment is opened or
+    /// sealed. Reads stamp the segment they use with the current epoch, so
+    /// recency is tracked without any shared counter on the read path.
+    epoch: u64,
+}
+
+#[derive(Debug)]
+struct SealedSegment {
+    size: u64,
+    open: Option<Segment>,
+    last_used: AtomicU64,
+}
+
+impl Segments {
+    fn advance(&mut self) -> u64 {
+        self.epoch += 1;
+        self.epoch
+    }
+
+    fn seal(&mut self, seq: u64, segment: Segment) -> Result<()> {
+        self.active.remove(&seq);
+        let sealed = SealedSegment {
+            size: segment.len()?,
+            open: Some(segment),
+            last_used: AtomicU64::new(self.advance()),
+        };
+        self.sealed.insert(seq, sealed);
+        self.close_least_recently_used();
+
+        Ok(())
+    }
+
+    fn close_least_recently_used(&mut self) {
+        let Some(max_open) = self.max_open else {
+            return;
+        };
+
+        let mut open = self
+            .sealed
+            .values_mut()
+            .filter(|sealed| sealed.open.is_some())
+            .collect::<Vec<_>>();
+        if open.len() <= max_open {
+            return;
+        }
+
+        open.sort_unstable_by_key(|sealed| sealed.last_used.load(Ordering::Relaxed));
+        let excess = open.len() - max_open;
+        for sealed in open.into_iter().take(excess) {
+            sealed.open = None;
+        }
+    }
+}
+
+/// A cheaply cloneable handle for reading records out of the log's segments.
+/// Reads use positional I/O, so any number of threads can share the same
+/// open files without seeking.
+#[derive(Debug, Clone)]
+pub struct LogReader {
+    vfs: Arc<dyn Vfs>,
+    path: Arc<PathBuf>,
+    segments: Arc<RwLock<Segments>>,
+    mmap: bool,
+}
+
+impl LogReader {
+    fn new(vfs: Arc<dyn Vfs>, path: impl AsRef<Path>, options: &KvStoreOptions) -> Self {
+        // A read-only log cannot reopen segments its owner has compacted
+        // away, so it keeps every segment open until it refreshes.
+        let max_open = match options.read_only {
+            true => None,
+            false => Some(options.max_open_segments.max(1)),
+        };
+        let segments = Segments {
+            active: BTreeMap::new(),
+            sealed: BTreeMap::new(),
+            max_open,
+            epoch: 0,
+        };
+
+        Self {
+            vfs,
+            path: Arc::new(path.as_ref().to_owned()),
+            segments: Arc::new(RwLock::new(segments)),
+            mmap: options.mmap,
+        }
+    }
+
+    fn add_segment(&self, seq: u64, file: Box<dyn VfsFile>, sealed: bool) -> Result<()> {
+        match sealed {
+            true => {
+                let segment = self.sealed_segment(file)?;
+                self.segments.write().unwrap().seal(seq, segment)
+            }
+            false => {
+                let segment = Segment::File(Arc::from(file));
+                self.segments.write().unwrap().active.insert(seq, segment);
+                Ok(())
+            }
+        }
+    }
+
+    fn sealed_segment(&self, file: Box<dyn VfsFile>) -> Result<Segment> {
+        match self.mmap {
+            true => Segment::map(file),
+            false => Ok(Segment::File(Arc::from(file))),
+        }
+    }
+
+    fn open_file(&self, seq: u64) -> Result<Box<dyn VfsFile>> {
+        self.vfs
+            .open(&get_log_path(&*self.path, seq))
+            .map_err(KvsError::OpenFile)
+    }
+
+    fn open_segment(&self, seq: u64) -> Result<()> {
+        let file = self.open_file(seq)?;
+        self.add_segment(seq, file, false)
+    }
+
+    /// Marks a segment as complete, switching it over to a memory map when
+    /// mapping is enabled. From then on it may be closed and reopened.
+    fn seal_segment(&self, seq: u64) -> Result<()> {
+        let active = {
+            let segments = self.segments.read().unwrap();
+            if segments.sealed.contains_key(&seq) {
+                return Ok(());
+            }
+            segments.active.get(&seq).cloned()
+        };
+
+        let segment = match active {
+            Some(segment) if !self.mmap => segment,
+            _ => self.sealed_segment(self.open_file(seq)?)?,
+        };
+        self.segments.write().unwrap().seal(seq, segment)
+    }
+
+    fn segment_seqs(&self) -> Vec<u64> {
+        let segments = self.segments.read().unwrap();
+        let mut seqs = segments
+            .active
+            .keys()
+            .chain(segments.sealed.keys())
+            .copied()
+            .collect::<Vec<_>>();
+
+        seqs.sort_unstable();
+        seqs
+    }
+
+    fn close_segment(&self, seq: u64) {
+        let mut segments = self.segments.write().unwrap();
+        segments.active.remove(&seq);
+        segments.sealed.remove(&seq);
+    }
+
+    fn segment(&self, seq: u64) -> Result<Segment> {
+        {
+            let segments = self.segments.read().unwrap();
+            if let Some(segment) = segments.active.get(&seq) {
+                return Ok(segment.clone());
+            }
+            let Some(sealed) = segments.sealed.get(&seq) else {
+                return Err(KvsError::SegmentNotFound(seq));
+            };
+            if let Some(segment) = &sealed.open {
+                // Skipping the store when nothing changed keeps hot segments'
+                // stamps from bouncing between cores.
+                if sealed.last_used.load(Ordering::Relaxed) != segments.epoch {
+                    sealed.last_used.store(segments.epoch, Ordering::Relaxed);
+                }
+                return Ok(segment.clone());
+            }
+        }
+
+        // Reopened without holding the lock, so reads from open segments
+        // carry on in the meantime.
+        let file = match self.open_file(seq) {
+            Err(KvsError::OpenFile(e)) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(KvsError::SegmentNotFound(seq))
+            }
+            file => file?,
+        };
+        let segment = self.sealed_segment(file)?;
+
+        let mut segments = self.segments.write().unwrap();
+        let last_used = segments.advance();
+        // The segment may have been closed for good while it was reopened.
+        let Some(sealed) = segments.sealed.get_mut(&seq) else {
+            return Err(KvsError::SegmentNotFound(seq));
+        };
+        *sealed.last_used.get_mut() = last_used;
+        let segment = sealed.open.get_or_insert(segment).clone();
+        segments.close_least_recently_used();
+
+        Ok(segment)
+    }
+
+    /// How many open segments are read through a memory map.
+    pub fn mapped_segments(&self) -> u64 {
+        let segments = self.segments.read().unwrap();
+        let mapped = segments
+            .sealed
+            .values()
+            .filter(|sealed| matches!(sealed.open, Some(Segment::Mapped(_))))
+            .count();
+        mapped as u64
+    }
+
+    /// The combined size of every segment.
+    pub fn disk_size(&self) -> Result<u64> {
+        let segments = self.segments.read().unwrap();
+        let active = segments
+            .active
+            .values()
+            .map(Segment::len)
+            .sum::<Result<u64>>()?;
+
+        let sealed = segments.sealed.values().map(|sealed| sealed.size);
+        Ok(active + sealed.sum::<u64>())
+    }
+
+    pub fn read(&self, log_pointer: &LogPointer) -> Result<Vec<u8>> {
+        let segment = self.segment(log_pointer.file_id)?;
+        segment.with_bytes(log_pointer, |bytes| Ok(bytes.to_vec()))
+    }
+
+    pub fn get(&self, log_pointer: &LogPointer) -> Result<LogCommand> {
+        let segment = self.segment(log_pointer.file_id)?;
+        segment.with_bytes(log_pointer, |bytes| {
+            record_options()
+                .deserialize(bytes)
+                .map_err(KvsError::ReadFromLog)
+        })
+    }
+
+    pub fn get_value(&self, log_pointer: &LogPointer) -> Result<Option<String>> {
+        let value = match self.get(log_pointer)? {
+            LogCommand::Set(_, value) | LogCommand::KeyspaceSet(_, _, value) => Some(value),
+            _ => None,
+        };
+
+        Ok(value)
+    }
+}
+
+/// The writing side of the log. Only one writable `Log` exists per store,
+/// and it hands out [`LogReader`]s for concurrent reads. A read-only `Log`
+/// has no writer, and follows the segments of the process that owns the
+/// store through [`Log::refresh`].
 #[derive(Debug)]
 pub struct Log {
+    vfs: Arc<dyn Vfs>,
     path: PathBuf,
-    readers: BTreeMap<u64, BufReader<File>>,
-    writer: BufWriter<File>,
+    _lock: Option<File>,
+    reader: LogReader,
+    writer: Option<BufWriter<Box<dyn VfsFile>>>,
     current_seq: u64,
+    sync: SyncPolicy,
+    unsynced_appends: u32,
+    max_segment_size: Option<u64>,
+    archive: Option<Archive>,
+    /// Where a failed append started, if its bytes could not be cut off the
+    /// active segment yet.
+    torn_tail: Option<u64>,
+    /// Set when the active segment outgrew `max_segment_size` but could not
+    /// be rotated yet.
+    rotate_due: bool,
+    /// Segments appends have moved off that could not be sealed or archived
+    /// yet. Retried whenever the log switches segments again.
+    unsealed: BTreeSet<u64>,
 }
 
 impl Log {
-    pub fn init(path: impl AsRef<Path>) -> Result<(u64, BTreeMap<String, LogPointer>, Self)> {
+    pub fn init(
+        path: impl AsRef<Path>,
+        options: &KvStoreOptions,
+    ) -> Result<(u64, Keyspaces, Self)> {
         let path = path.as_ref();
-        fs::create_dir_all(path)?;
+        let vfs = Arc::clone(&options.vfs);
 
-        let log_seqs = scan_log_seqs(path)?;
-        let current_seq = log_seqs.last().unwrap_or(&0) + 1;
-        let mut readers = open_log_readers(path, &log_seqs)?;
-        let (uncompacted_bytes, index) = build_index(&mut readers)?;
+        if !options.read_only {
+            vfs.create_dir_all(path)?;
+        }
+        let lock = vfs.lock_dir(path, options.lock_timeout, options.read_only)?;
 
-        let (reader, writer) = new_log_pair(path, current_seq)?;
-        readers.insert(current_seq, reader);
+        let log_seqs = scan_log_seqs(&*vfs, path)?;
+        let last_seq = log_seqs.last().copied();
+        let reader = LogReader::new(Arc::clone(&vfs), path, options);
+
+        let (uncompacted_bytes, index) = match options.read_only {
+            // The owning process may compact at any moment, so every segment
+            // is opened before any of them is read.
+            true => {
+                let mut readers = open_log_readers(&*vfs, path, &log_seqs)?;
+                let built = build_index(&mut readers)?;
+                for (seq, log_reader) in readers {
+                    // The newest segment may still be growing.
+                    let sealed = Some(seq) != last_seq;
+                    reader.add_segment(seq, log_reader.into_inner(), sealed)?;
+                }
+                built
+            }
+            // With the store locked, segments can be read one at a time, and
+            // no more are left open than the reader keeps.
+            false => {
+                let index = Keyspaces::new();
+                let mut uncompacted_bytes = 0;
+                for &seq in &log_seqs {
+                    let mut log_reader = new_log_reader(&*vfs, path, seq)?;
+                    uncompacted_bytes += index_segment(&index, seq, &mut log_reader)?;
+                    reader.add_segment(seq, log_reader.into_inner(), true)?;
+                }
+                (uncompacted_bytes, index)
+            }
+        };
+
+        let mut archive = None;
+        let (current_seq, writer) = match options.read_only {
+            true => (last_seq.unwrap_or(0), None),
+            false => {
+                // Whatever is on disk was sealed when its writer went away.
+                if let Some(dir) = &options.archive_dir {
+                    let archive = archive.insert(Archive::open(dir)?);
+                    for &seq in &log_seqs {
+                        archive.archive_segment(path, seq)?;
+                    }
+                }
+
+                let current_seq = last_seq.unwrap_or(0) + 1;
+                let writer = new_log_writer(&*vfs, path, current_seq)?;
+                reader.open_segment(current_seq)?;
+                (current_seq, Some(writer))
+            }
+        };
 
         let log = Self {
+            vfs,
             path: path.to_owned(),
-            readers,
+            _lock: lock,
+            reader,
             writer,
             current_seq,
+            sync: options.sync,
+            unsynced_appends: 0,
+            max_segment_size: options.max_segment_size,
+            archive,
+            torn_tail: None,
+            rotate_due: false,
+            unsealed: BTreeSet::new(),
         };
 
         Ok((uncompacted_bytes, index, log))
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.writer.is_none()
+    }
+
+    /// Rebuilds the index from the segments currently on disk, so a
+    /// read-only log picks up records appended by the owning process.
+    /// Segments that have since been removed stay open until
+    /// [`Log::close_removed_segments`] is called.
+    pub fn refresh(&mut self) -> Result<(u64, Keyspaces)> {
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            match self.try_refresh() {
+                // The owner compacted between listing and opening segments.
+                Err(KvsError::IoError(e))
+                    if e.kind() == io::ErrorKind::NotFound && attempts < REFRESH_ATTEMPTS =>
+                {
+                    continue
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn try_refresh(&mut self) -> Result<(u64, Keyspaces)> {
+        let log_seqs = scan_log_seqs(&*self.vfs, &self.path)?;
+        let last_seq = log_seqs.last().copied();
+        let mut readers = open_log_readers(&*self.vfs, &self.path, &log_seqs)?;
+        let (uncompacted_bytes, index) = build_index(&mut readers)?;
+        let open_seqs = self.reader.segment_seqs();
+
+        for (seq, log_reader) in readers {
+            let sealed = Some(seq) != last_seq;
+
+            match open_seqs.contains(&seq) {
+                true if sealed => self.reader.seal_segment(seq)?,
+                true => {}
+                false => self
+                    .reader
+                    .add_segment(seq, log_reader.into_inner(), sealed)?,
+            }
+        }
+
+        self.current_seq = last_seq.unwrap_or(0);
+        Ok((uncompacted_bytes, index))
+    }
+
+    pub fn close_removed_segments(&mut self) -> Result<()> {
+        let log_seqs = scan_log_seqs(&*self.vfs, &self.path)?;
+
+        for seq in self.reader.segment_seqs() {
+            if !log_seqs.contains(&seq) {
+                self.reader.close_segment(seq);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn writer(&mut self) -> Result<&mut BufWriter<Box<dyn VfsFile>>> {
+        self.writer.as_mut().ok_or(KvsError::ReadOnly)
+    }
+
+    pub fn reader(&self) -> LogReader {
+        self.reader.clone()
+    }
+
+    /// Appends a record to the active segment. If that fails part way, the
+    /// segment is cut back to where the record started, so that the next
+    /// append lands on a clean tail; running out of space fails with
+    /// `KvsError::DiskFull`, and appending works again once space is freed.
+    /// Once a record is written its pointer is returned, even if rotating a
+    /// full segment afterwards fails; opening the next segment is retried on
+    /// the next append, and sealing or archiving the full one whenever the
+    /// log next switches segments.
     pub fn append(&mut self, log_command: LogCommand) -> Result<LogPointer> {
-        let offset = self.writer.stream_position()?;
-        bincode::serialize_into(&mut self.writer, &log_command).map_err(KvsError::AppendToLog)?;
+        self.writer()?;
+        self.roll_back_torn_tail().map_err(KvsError::or_disk_full)?;
+        if self.rotate_due {
+            self.rotate().map_err(KvsError::or_disk_full)?;
+        }
+
+        let current_seq = self.current_seq;
+        let offset = self.writer()?.stream_position()?;
 
-        let length = self.writer.stream_position()? - offset;
-        let pointer = LogPointer::new(self.current_seq, offset, length);
-        self.writer.flush()?;
+        if let Err(e) = self.write_record(&log_command) {
+            self.torn_tail = Some(offset);
+            // If this fails too, the next append tries again before writing.
+            let _ = self.roll_back_torn_tail();
+            return Err(e.or_disk_full());
+        }
+
+        let length = self.writer()?.stream_position()? - offset;
+        let pointer = LogPointer::new(current_seq, offset, length);
+
+        if self
+            .max_segment_size
+            .is_some_and(|max_size| offset + length >= max_size)
+        {
+            self.rotate_due = true;
+            // The record is in the log either way, so failing now would only
+            // report a write as lost that reappears on the next open.
+            let _ = self.rotate();
+        }
 
         Ok(pointer)
     }
 
-    pub fn get(&mut self, log_pointer: &LogPointer) -> Result<LogCommand> {
-        let reader = self.readers.get_mut(&log_pointer.file_id).unwrap();
-        reader.seek(SeekFrom::Start(log_pointer.offset))?;
-        let value = bincode::deserialize_from(reader).map_err(KvsError::ReadFromLog)?;
+    fn write_record(&mut self, log_command: &LogCommand) -> Result<()> {
+        let writer = self.writer()?;
+        bincode::serialize_into(&mut *writer, log_command).map_err(KvsError::AppendToLog)?;
+        writer.flush()?;
 
-        Ok(value)
+        self.sync_after_append()
     }
 
-    pub fn get_value(&mut self, log_pointer: &LogPointer) -> Result<Option<String>> {
-        let value = match self.get(log_pointer)? {
-            LogCommand::Set(_, value) => Some(value),
-            _ => None,
+    /// Cuts the active segment back to where a failed append started,
+    /// dropping whatever part of the record is still buffered or made it
+    /// to the file.
+    fn roll_back_torn_tail(&mut self) -> Result<()> {
+        let Some(offset) = self.torn_tail else {
+            return Ok(());
         };
 
-        Ok(value)
+        let writer = self.writer.take().ok_or(KvsError::ReadOnly)?;
+        let (mut file, _) = writer.into_parts();
+        let truncated = file
+            .set_len(offset)
+            .and_then(|()| file.seek(SeekFrom::Start(offset)));
+        self.writer = Some(BufWriter::new(file));
+        truncated?;
+
+        self.torn_tail = None;
+        Ok(())
+    }
+
+    fn sync_after_append(&mut self) -> Result<()> {
+        self.unsynced_appends += 1;
+
+        let due = match self.sync {
+            SyncPolicy::Never => false,
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(appends) => self.unsynced_appends >= appends,
+        };
+
+        if due {
+            self.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> Result<()> {
+        self.writer()?.get_ref().sync_data()?;
+        self.unsynced_appends = 0;
+
+        Ok(())
+    }
+
+    /// Seals the active segment, so that everything written so far sits in
+    /// segments that never change again. Returns the end of the log and the
+    /// segments that hold everything before it.
+    pub fn freeze(&mut self) -> Result<(Lsn, Vec<u64>)> {
+        self.roll_back_torn_tail()?;
+        let seq = self.current_seq;
+        let offset = self.writer()?.stream_position()?;
+        self.rotate()?;
+
+        let seqs = self
+            .reader
+            .segment_seqs()
+            .into_iter()
+            .filter(|&segment_seq| segment_seq <= seq)
+            .collect();
+
+        Ok((Lsn { seq, offset }, seqs))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens a fresh reader on every segment, independent of the ones reads
+    /// go through.
+    pub fn open_readers(&self) -> Result<BTreeMap<u64, io::BufReader<Box<dyn VfsFile>>>> {
+        open_log_readers(&*self.vfs, &self.path, &self.reader.segment_seqs())
+    }
+
+    /// Seals the active segment and continues appending to a fresh one.
+    fn rotate(&mut self) -> Result<()> {
+        self.roll_back_torn_tail()?;
+        if self.sync != SyncPolicy::Never {
+            self.sync_data()?;
+        }
+
+        let next_seq = self.current_seq + 1;
+        self.switch_segment(next_seq)
+    }
+
+    /// Moves appends over to a fresh segment `next_seq`, then seals the
+    /// active one. The writer and `current_seq` change together, so a
+    /// failure to seal or archive never leaves appends pointing at the
+    /// wrong segment; the old segment is kept in `unsealed` and retried on
+    /// the next switch instead, as appends can carry on without it.
+    fn switch_segment(&mut self, next_seq: u64) -> Result<()> {
+        let writer = self.new_log_file(next_seq)?;
+        let sealed_seq = std::mem::replace(&mut self.current_seq, next_seq);
+        self.writer = Some(writer);
+        self.rotate_due = false;
+
+        self.unsealed.insert(sealed_seq);
+        let _ = self.seal_pending();
+        Ok(())
     }
 
-    pub fn new_log_file(&mut self, new_seq: u64) -> Result<BufWriter<File>> {
-        let (reader, writer) = new_log_pair(&self.path, new_seq)?;
-        self.readers.insert(new_seq, reader);
+    /// Seals and archives every segment in `unsealed`, oldest first, so the
+    /// archive gains them in order.
+    fn seal_pending(&mut self) -> Result<()> {
+        while let Some(&seq) = self.unsealed.first() {
+            self.reader.seal_segment(seq)?;
+            self.archive_segment(seq)?;
+            self.unsealed.remove(&seq);
+        }
+
+        Ok(())
+    }
+
+    fn archive_segment(&mut self, seq: u64) -> Result<()> {
+        match &mut self.archive {
+            Some(archive) => archive.archive_segment(&self.path, seq),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get(&self, log_pointer: &LogPointer) -> Result<LogCommand> {
+        self.reader.get(log_pointer)
+    }
+
+    pub fn get_value(&self, log_pointer: &LogPointer) -> Result<Option<String>> {
+        self.reader.get_value(log_pointer)
+    }
+
+    pub fn new_log_file(&mut self, new_seq: u64) -> Result<BufWriter<Box<dyn VfsFile>>> {
+        let writer = new_log_writer(&*self.vfs, &self.path, new_seq)?;
+        self.reader.open_segment(new_seq)?;
 
         Ok(writer)
     }
 
-    pub fn prepare_commit(&mut self) -> Result<(u64, BufWriter<File>)> {
+    pub fn prepare_commit(&mut self) -> Result<(u64, BufWriter<Box<dyn VfsFile>>)> {
+        self.roll_back_torn_tail()?;
         let commit_seq = self.current_seq + 1;
-        let next_writer_seq = self.current_seq + 2;
+        if let Some(archive) = &mut self.archive {
+            archive.reserve_segment(commit_seq)?;
+        }
+        self.switch_segment(commit_seq + 1)?;
+        // Compaction removes every segment before the commit, so they have
+        // to be archived first.
+        self.seal_pending()?;
 
-        self.writer = self.new_log_file(next_writer_seq)?;
-        self.current_seq = next_writer_seq;
-        let commit_file = self.new_log_file(commit_seq)?;
+        let commit_path = get_commit_path(&self.path, commit_seq);
+        let commit_file = self.vfs.create(&commit_path).map_err(KvsError::OpenFile)?;
+        let reader = self.vfs.open(&commit_path).map_err(KvsError::OpenFile)?;
+        self.reader.add_segment(commit_seq, reader, false)?;
 
-        Ok((commit_seq, commit_file))
+        Ok((commit_seq, BufWriter::new(commit_file)))
     }
 
     pub fn stage_to_commit_file(
-        &mut self,
-        commit_file: &mut BufWriter<File>,
+        &self,
+        commit_file: &mut BufWriter<Box<dyn VfsFile>>,
         pointer: &LogPointer,
     ) -> Result<u64> {
-        let reader = self.readers.get_mut(&pointer.file_id).unwrap();
-        reader.seek(SeekFrom::Start(pointer.offset))?;
-        let mut slice = reader.take(pointer.length);
-        let bytes_written = std::io::copy(&mut slice, commit_file)?;
+        let buffer = self.reader.read(pointer)?;
+        commit_file.write_all(&buffer)?;
+
+        Ok(buffer.len() as u64)
+    }
+
+    pub fn seal_commit_file(
+        &mut self,
+        commit_seq: u64,
+        mut commit_file: BufWriter<Box<dyn VfsFile>>,
+    ) -> Result<()> {
+        commit_file.flush()?;
+        if self.sync != SyncPolicy::Never {
+            commit_file.get_ref().sync_data()?;
+        }
+        self.vfs.rename(
+            &get_commit_path(&self.path, commit_seq),
+            &get_log_path(&self.path, commit_seq),
+        )?;
+
+        // The commit is complete either way, so sealing and archiving it
+        // are left to the next switch if they fail now.
+        self.unsealed.insert(commit_seq);
+        let _ = self.seal_pending();
+        Ok(())
+    }
+
+    /// Throws away the commit file of a compaction that failed before it was
+    /// sealed. The segments it was copying from are left as they were.
+    pub fn abort_commit(&mut self, commit_seq: u64) -> Result<()> {
+        let commit_path = get_commit_path(&self.path, commit_seq);
+        // Once renamed, the segment stays open so the next compaction removes it.
+        if !self.vfs.exists(&commit_path) {
+            return Ok(());
+        }
 
-        Ok(bytes_written)
+        self.reader.close_segment(commit_seq);
+        self.vfs.remove_file(&commit_path)?;
+        Ok(())
     }
 
+    /// Removes every segment before `commit_seq`. With an archive, nothing
+    /// is removed unless all of them have been archived, as the archive
+    /// would be left with a gap otherwise.
     pub fn remove_stale_logs(&mut self, commit_seq: u64) -> Result<()> {
         let stale_seqs = self
-            .readers
-            .keys()
-            .filter(|&&seq| seq < commit_seq)
-            .cloned()
+            .reader
+            .segment_seqs()
+            .into_iter()
+            .filter(|&seq| seq < commit_seq)
             .collect::<Vec<_>>();
+        if let Some(archive) = &self.archive {
+            if let Some(&seq) = stale_seqs.iter().find(|&&seq| !archive.is_archived(seq)) {
+                return Err(KvsError::NotArchived(seq));
+            }
+        }
 
         for seq in stale_seqs {
-            self.readers.remove(&seq);
-            remove_log_file(&self.path, seq)?;
+            self.reader.close_segment(seq);
+            remove_log_file(&*self.vfs, &self.path, seq)?;
         }
 
         Ok(())
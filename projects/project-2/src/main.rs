@@ -1,20 +1,296 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
-use project_2::{Cli, KvStore, KvsError};
+use output::Output;
+use project_2::{
+    engines::{MemoryKvsEngine, SledKvsEngine},
+    log::DEFAULT_KEYSPACE,
+    resolve_format, Cli, Command, Config, Engine, KvStore, KvStoreOptions, KvsEngine, KvsError,
+    OnConflict, OutputFormat,
+};
+use serde_json::json;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Seek},
+    path::Path,
+    process::{self, ExitCode},
+    time::Duration,
+};
 
-fn main() -> Result<()> {
+mod output;
+mod shell;
+
+fn main() -> ExitCode {
     let args = Cli::parse();
-    let path = std::env::current_dir()?;
-    let mut store = KvStore::open(path)?;
+    let format = args.output;
+
+    match try_main(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            output::print_error(&e, format);
+            output::exit_status(&e)
+        }
+    }
+}
+
+fn try_main(args: Cli) -> Result<()> {
+    let format = args.output;
+    let profile = Config::load_default()?.profile(args.profile.as_deref())?;
+    let engine = args.engine.or(profile.engine).unwrap_or(Engine::Kvs);
+    let path = match args.dir.or(profile.dir) {
+        Some(dir) => dir,
+        None => std::env::current_dir()?,
+    };
 
-    match args {
-        Cli::Set(args) => store.set(&args.key, &args.value)?,
-        Cli::Rm(args) => store.remove(&args.key)?,
-        Cli::Get(args) => {
-            let value = store.get(&args.key)?.ok_or(KvsError::KeyNotFound)?;
-            println!("{value}");
+    let uses_keyspaces = args.keyspace != DEFAULT_KEYSPACE || args.command.requires_kvs();
+
+    if engine != Engine::Kvs && uses_keyspaces {
+        return Err(KvsError::Unsupported(engine.name()).into());
+    }
+
+    // Only a write can create a store; reads against the kvs engine never touch the directory.
+    let creates_store = engine == Engine::Sled || !args.command.is_read_only();
+    if engine != Engine::Memory && creates_store && !args.init {
+        check_store_dir(&path)?;
+    }
+
+    let output = match engine {
+        // Restoring replaces the store's files, so it runs without opening it.
+        Engine::Kvs if matches!(args.command, Command::Restore(_)) => {
+            let Command::Restore(restore) = args.command else {
+                unreachable!()
+            };
+            match restore.until {
+                Some(until) => {
+                    let lsn = KvStore::restore_archive(&restore.source, &path, until)?;
+                    Output::Report(
+                        format!("Restored archive to LSN {lsn}"),
+                        json!({ "lsn": lsn.to_string() }),
+                    )
+                }
+                None => {
+                    let manifest = KvStore::restore_from(&restore.source, &path)?;
+                    Output::Report(
+                        format!("Restored backup {} at LSN {}", manifest.id, manifest.lsn),
+                        json!({ "id": manifest.id, "lsn": manifest.lsn.to_string() }),
+                    )
+                }
+            }
+        }
+        Engine::Kvs if matches!(args.command, Command::Verify) => {
+            let report = KvStore::verify(&path)?;
+            Output::verify(&report).print(format)?;
+            match report.problems.len() {
+                0 => Output::None,
+                problems => return Err(KvsError::Corrupt { problems }.into()),
+            }
+        }
+        Engine::Kvs if matches!(args.command, Command::Repair) => {
+            Output::repair(&KvStore::repair(&path)?)
         }
+        Engine::Kvs => {
+            let mut options = match &args.config {
+                Some(config) => KvStoreOptions::from_toml_file(config)?,
+                None => profile.options,
+            };
+            if args.command.is_read_only() {
+                options.read_only(true);
+            }
+            if let Some(wait) = args.wait {
+                options.lock_timeout(Duration::from_secs(wait));
+            }
+            let store = options.open(path)?;
+            run_kvs(&store, &args.keyspace, args.command, format)?
+        }
+        Engine::Sled => run(&SledKvsEngine::open(path)?, args.command)?,
+        Engine::Memory => run(&MemoryKvsEngine::new(), args.command)?,
     };
 
+    output.print(format)?;
     Ok(())
 }
+
+/// Refuses to create a store in a directory that already holds unrelated files,
+/// which usually means `kvs` was run in the wrong place.
+fn check_store_dir(path: &Path) -> Result<()> {
+    if Engine::recorded(path)?.is_some() || !path.is_dir() {
+        return Ok(());
+    }
+
+    match fs::read_dir(path)?.next() {
+        Some(_) => Err(KvsError::NotAStore(path.to_owned()).into()),
+        None => Ok(()),
+    }
+}
+
+/// Copies stdin to a temporary file, so an import can read it twice.
+fn spool_stdin() -> Result<BufReader<File>> {
+    let path = env::temp_dir().join(format!("kvs-import-{}", process::id()));
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let copied = io::copy(&mut io::stdin().lock(), &mut file);
+    // The open handle keeps the data around where the OS allows unlinking it.
+    let _ = fs::remove_file(&path);
+    copied?;
+
+    file.rewind()?;
+    Ok(BufReader::new(file))
+}
+
+fn run_kvs(
+    store: &KvStore,
+    keyspace_name: &str,
+    command: Command,
+    format: OutputFormat,
+) -> Result<Output> {
+    let keyspace = store.keyspace(keyspace_name);
+
+    let output = match command {
+        Command::Keyspaces => Output::Keys(store.keyspaces()),
+        Command::DropKeyspace(args) => {
+            store.drop_keyspace(&args.name)?;
+            Output::None
+        }
+        Command::Keys(args) if args.delete => {
+            let regex = args.regex.expect("--delete requires --regex");
+            let count = keyspace.remove_matching(&regex)?;
+            Output::Report(format!("Removed {count} keys"), json!({ "removed": count }))
+        }
+        Command::Keys(args) => {
+            let keys = match &args.regex {
+                Some(regex) => keyspace.keys_matching(regex),
+                None => keyspace.keys_with_prefix(&args.prefix),
+            };
+            let keys = keys.into_iter().filter(|key| key.starts_with(&args.prefix));
+
+            match args.values {
+                true => {
+                    let mut pairs = Vec::new();
+                    for key in keys {
+                        if let Some(value) = keyspace.get(&key)? {
+                            pairs.push((key, value));
+                        }
+                    }
+                    Output::Pairs(pairs)
+                }
+                false => Output::Keys(keys.collect()),
+            }
+        }
+        Command::Scan(args) => Output::Pairs(keyspace.scan(&args.prefix)?),
+        Command::Exists(args) => Output::Exists(keyspace.contains_key(&args.key)),
+        Command::Count => Output::Count(keyspace.len() as u64),
+        Command::Stats => Output::stats(store.stats()),
+        Command::Compact => {
+            let before = store.disk_size()?;
+            store.compact()?;
+            let after = store.disk_size()?;
+            Output::Report(
+                format!("Compacted {before} bytes to {after} bytes"),
+                json!({ "before_bytes": before, "after_bytes": after }),
+            )
+        }
+        Command::Dump => {
+            store.dump(io::stdout().lock())?;
+            Output::None
+        }
+        Command::Load => {
+            let count = store.load(io::stdin().lock())?;
+            Output::Report(format!("Loaded {count} keys"), json!({ "loaded": count }))
+        }
+        Command::Export(args) => {
+            let format = resolve_format(args.format, args.file.as_deref());
+
+            match &args.file {
+                Some(file) => {
+                    let count = store.export(BufWriter::new(File::create(file)?), format)?;
+                    Output::Report(
+                        format!("Exported {count} keys"),
+                        json!({ "exported": count }),
+                    )
+                }
+                None => {
+                    store.export(io::stdout().lock(), format)?;
+                    Output::None
+                }
+            }
+        }
+        Command::Import(args) => {
+            let format = resolve_format(args.format, args.file.as_deref());
+            let on_conflict = match args.fail_on_conflict {
+                true => OnConflict::Fail,
+                false => OnConflict::Overwrite,
+            };
+
+            let count = match (&args.file, on_conflict) {
+                (Some(file), _) => {
+                    store.import(BufReader::new(File::open(file)?), format, on_conflict)?
+                }
+                (None, OnConflict::Overwrite) => store.import_stream(io::stdin().lock(), format)?,
+                (None, OnConflict::Fail) => store.import(spool_stdin()?, format, on_conflict)?,
+            };
+            Output::Report(
+                format!("Imported {count} keys"),
+                json!({ "imported": count }),
+            )
+        }
+        Command::Backup(args) => {
+            let manifest = match &args.since {
+                Some(since) => store.incremental_backup_to(&args.backup, since)?,
+                None => store.backup_to(&args.backup)?,
+            };
+            let on_top = match &manifest.parent {
+                Some(parent) => format!(" on top of {parent}"),
+                None => String::new(),
+            };
+            Output::Report(
+                format!(
+                    "Backed up {} segments ({} bytes) as {} at LSN {}{on_top}",
+                    manifest.segments.len(),
+                    manifest.total_size(),
+                    manifest.id,
+                    manifest.lsn
+                ),
+                json!({
+                    "id": manifest.id,
+                    "parent": manifest.parent,
+                    "lsn": manifest.lsn.to_string(),
+                    "segments": manifest.segments.len(),
+                    "bytes": manifest.total_size(),
+                }),
+            )
+        }
+        Command::Restore(_) => bail!("Cannot restore into a store that is open"),
+        Command::Verify => bail!("Cannot verify a store that is open"),
+        Command::Repair => bail!("Cannot repair a store that is open"),
+        Command::Shell(args) => {
+            shell::run(store, keyspace_name, args.batch.as_deref(), format)?;
+            Output::None
+        }
+        command => run(&keyspace, command)?,
+    };
+
+    Ok(output)
+}
+
+fn run(engine: &impl KvsEngine, command: Command) -> Result<Output> {
+    let output = match command {
+        Command::Set(args) => {
+            engine.set(&args.key, &args.value)?;
+            Output::None
+        }
+        Command::Rm(args) => {
+            engine.remove(&args.key)?;
+            Output::None
+        }
+        Command::Get(args) => {
+            let value = engine.get(&args.key)?.ok_or(KvsError::KeyNotFound)?;
+            Output::Value(value)
+        }
+        _ => unreachable!("{command:?} is only run against the kvs engine"),
+    };
+
+    Ok(output)
+}
@@ -0,0 +1,239 @@
+use crate::{log::MAX_FIELD_SIZE, KvStore, KvsError, OsVfs, Result, Vfs};
+use serde::{Deserialize, Deserializer};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+const DEFAULT_COMPACTION_THRESHOLD: u64 = 1_024 * 1_024;
+const DEFAULT_MAX_KEY_SIZE: u64 = 64 * 1_024;
+const DEFAULT_MAX_VALUE_SIZE: u64 = 64 * 1_024 * 1_024;
+const DEFAULT_MAX_OPEN_SEGMENTS: usize = 128;
+
+/// When appended records are forced to stable storage with `fsync`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    /// Records are flushed to the OS after every write, which survives a
+    /// process crash but not a power failure.
+    #[default]
+    Never,
+    /// Every write is synced before it returns.
+    Always,
+    /// Writes are synced after every given number of appends.
+    Every(u32),
+}
+
+/// Settings used when opening a [`KvStore`], created by [`KvStore::options`].
+/// The same settings can be read from a TOML file with
+/// [`KvStoreOptions::from_toml_file`], using the setter names as keys:
+///
+/// ```toml
+/// cache_size = 1048576
+/// max_segment_size = 67108864
+/// max_disk_size = 1073741824
+/// sync = { every = 100 }
+/// lock_timeout = 5
+/// archive_dir = "/var/backups/kvs/archive"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KvStoreOptions {
+    pub(crate) create_if_missing: bool,
+    pub(crate) error_if_exists: bool,
+    pub(crate) read_only: bool,
+    pub(crate) auto_compact: bool,
+    pub(crate) compaction_threshold: u64,
+    pub(crate) sync: SyncPolicy,
+    pub(crate) max_segment_size: Option<u64>,
+    pub(crate) max_disk_size: Option<u64>,
+    pub(crate) max_key_size: u64,
+    pub(crate) max_value_size: u64,
+    pub(crate) mmap: bool,
+    pub(crate) max_open_segments: usize,
+    pub(crate) cache_size: u64,
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub(crate) lock_timeout: Option<Duration>,
+    pub(crate) archive_dir: Option<PathBuf>,
+    #[serde(skip, default = "default_vfs")]
+    pub(crate) vfs: Arc<dyn Vfs>,
+}
+
+impl Default for KvStoreOptions {
+    fn default() -> Self {
+        Self {
+            create_if_missing: true,
+            error_if_exists: false,
+            read_only: false,
+            auto_compact: true,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            sync: SyncPolicy::default(),
+            max_segment_size: None,
+            max_disk_size: None,
+            max_key_size: DEFAULT_MAX_KEY_SIZE,
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+            mmap: false,
+            max_open_segments: DEFAULT_MAX_OPEN_SEGMENTS,
+            cache_size: 0,
+            lock_timeout: None,
+            archive_dir: None,
+            vfs: default_vfs(),
+        }
+    }
+}
+
+fn default_vfs() -> Arc<dyn Vfs> {
+    Arc::new(OsVfs)
+}
+
+fn deserialize_secs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Duration>, D::Error> {
+    let secs = Option::<f64>::deserialize(deserializer)?;
+    Ok(secs.map(Duration::from_secs_f64))
+}
+
+impl KvStoreOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses options from TOML. Keys that are left out keep their defaults.
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml).map_err(KvsError::Config)
+    }
+
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self> {
+        let toml = fs::read_to_string(path).map_err(KvsError::OpenFile)?;
+        Self::from_toml(&toml)
+    }
+
+    /// Creates the store if the directory does not hold one yet. Defaults to `true`.
+    pub fn create_if_missing(&mut self, create_if_missing: bool) -> &mut Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// Fails with `KvsError::StoreExists` if the directory already holds a store.
+    pub fn error_if_exists(&mut self, error_if_exists: bool) -> &mut Self {
+        self.error_if_exists = error_if_exists;
+        self
+    }
+
+    /// Opens the store for reading only. See [`KvStore::open_read_only`].
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Compacts automatically once the threshold of stale bytes is passed.
+    /// Defaults to `true`; with it off, only [`KvStore::compact`] compacts.
+    pub fn auto_compact(&mut self, auto_compact: bool) -> &mut Self {
+        self.auto_compact = auto_compact;
+        self
+    }
+
+    /// The number of bytes of overwritten or removed records that triggers
+    /// automatic compaction. Defaults to 1 MiB.
+    pub fn compaction_threshold(&mut self, bytes: u64) -> &mut Self {
+        self.compaction_threshold = bytes;
+        self
+    }
+
+    pub fn sync(&mut self, sync: SyncPolicy) -> &mut Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Starts a new segment once the active one reaches `bytes`. Segments
+    /// grow without limit by default.
+    pub fn max_segment_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_segment_size = Some(bytes);
+        self
+    }
+
+    /// Caps the space the store's segments may take up. A write that would
+    /// go past it compacts first, then fails with `KvsError::QuotaExceeded`
+    /// if that did not free enough. Removes are always allowed, since
+    /// compacting them away is how space comes back.
+    pub fn max_disk_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_disk_size = Some(bytes);
+        self
+    }
+
+    /// The longest key, and keyspace name, in bytes, that `set` accepts.
+    /// Defaults to 64 KiB.
+    /// Limits past [`MAX_FIELD_SIZE`](crate::log::MAX_FIELD_SIZE) are
+    /// lowered to it.
+    pub fn max_key_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_key_size = bytes;
+        self
+    }
+
+    /// The longest value, in bytes, that `set` accepts. Defaults to 64 MiB,
+    /// and is capped like [`KvStoreOptions::max_key_size`].
+    pub fn max_value_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_value_size = bytes;
+        self
+    }
+
+    /// Limits past what the log can read back are lowered to it.
+    pub(crate) fn size_limits(&self) -> (u64, u64) {
+        (
+            self.max_key_size.min(MAX_FIELD_SIZE),
+            self.max_value_size.min(MAX_FIELD_SIZE),
+        )
+    }
+
+    /// Reads sealed segments through memory maps rather than `read_at`. The
+    /// active segment is always read with normal file I/O.
+    pub fn mmap(&mut self, mmap: bool) -> &mut Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Keeps at most `count` sealed segments open, closing the least
+    /// recently read and reopening them when needed. Defaults to 128.
+    /// Read-only handles keep every segment open, as they cannot reopen
+    /// one the owning process has compacted away.
+    pub fn max_open_segments(&mut self, count: usize) -> &mut Self {
+        self.max_open_segments = count;
+        self
+    }
+
+    /// Caches recently read values in memory, up to `bytes` of keys and
+    /// values. A size of zero, the default, disables the cache.
+    pub fn cache_size(&mut self, bytes: u64) -> &mut Self {
+        self.cache_size = bytes;
+        self
+    }
+
+    /// Waits up to `timeout` for another process to release the store,
+    /// rather than failing straight away with `KvsError::Locked`.
+    pub fn lock_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Copies every segment into `dir` once it is sealed, for restoring to
+    /// a point in time with [`KvStore::restore_archive`]. Segments already
+    /// in the store are archived when it is opened.
+    pub fn archive_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.archive_dir = Some(dir.into());
+        self
+    }
+
+    /// Keeps the log on `vfs` rather than the OS filesystem, such as a
+    /// [`MemoryVfs`](crate::MemoryVfs) for a store that lives in memory.
+    /// Backups and archiving still need the OS filesystem.
+    pub fn vfs(&mut self, vfs: impl Vfs + 'static) -> &mut Self {
+        self.vfs = Arc::new(vfs);
+        self
+    }
+
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<KvStore> {
+        KvStore::open_with_options(path.as_ref(), self)
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::Error;
+use project_2::{KvsError, OutputFormat, RepairReport, Stats, VerifyReport};
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    process::ExitCode,
+};
+
+/// The result of a command, kept structured until it is printed in the
+/// format chosen with `--output`.
+#[derive(Debug)]
+pub enum Output {
+    None,
+    Value(String),
+    Keys(Vec<String>),
+    Pairs(Vec<(String, String)>),
+    Exists(bool),
+    Count(u64),
+    /// A summary: a sentence for plain output and an object for JSON.
+    Report(String, Value),
+}
+
+impl Output {
+    pub fn stats(stats: Stats) -> Self {
+        let mut text = format!(
+            "keyspaces: {}\nkeys: {}\ndisk bytes: {}\nuncompacted bytes: {}",
+            stats.keyspaces, stats.keys, stats.disk_bytes, stats.uncompacted_bytes
+        );
+        let mut fields = json!({
+            "keyspaces": stats.keyspaces,
+            "keys": stats.keys,
+            "disk_bytes": stats.disk_bytes,
+            "uncompacted_bytes": stats.uncompacted_bytes,
+        });
+
+        if let Some(quota) = stats.disk_quota {
+            text += &format!("\ndisk quota: {quota}");
+            fields["disk_quota"] = json!(quota);
+        }
+
+        if let Some(cache) = stats.cache {
+            text += &format!(
+                "\ncache bytes: {} of {}\ncache hit rate: {:.2}",
+                cache.size_bytes,
+                cache.capacity_bytes,
+                cache.hit_rate()
+            );
+            fields["cache"] = json!({
+                "capacity_bytes": cache.capacity_bytes,
+                "size_bytes": cache.size_bytes,
+                "entries": cache.entries,
+                "hits": cache.hits,
+                "misses": cache.misses,
+            });
+        }
+
+        Self::Report(text, fields)
+    }
+
+    pub fn verify(report: &VerifyReport) -> Self {
+        let mut text = format!(
+            "segments: {}\nrecords: {} ({} live)\ntotal bytes: {}\nunreachable bytes: {}",
+            report.segments,
+            report.records,
+            report.live_records,
+            report.total_bytes,
+            report.unreachable_bytes
+        );
+        for problem in &report.problems {
+            text += &format!("\nproblem: {problem}");
+        }
+
+        let fields = serde_json::to_value(report).unwrap_or_default();
+        Self::Report(text, fields)
+    }
+
+    pub fn repair(report: &RepairReport) -> Self {
+        let mut text = format!(
+            "Recovered {} keys, salvaging {} records from damaged segments and losing {} bytes",
+            report.keys, report.salvaged_records, report.lost_bytes
+        );
+        for file in &report.quarantined {
+            text += &format!("\nquarantined: {}", file.display());
+        }
+
+        let fields = serde_json::to_value(report).unwrap_or_default();
+        Self::Report(text, fields)
+    }
+
+    pub fn print(&self, format: OutputFormat) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+
+        match format {
+            OutputFormat::Json => self.write_json(&mut stdout),
+            OutputFormat::Raw => match self {
+                Self::Value(value) => write!(stdout, "{value}"),
+                output => output.write_plain(&mut stdout),
+            },
+            OutputFormat::Plain => self.write_plain(&mut stdout),
+        }
+    }
+
+    fn write_plain(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::Value(value) => writeln!(out, "{value}"),
+            Self::Keys(keys) => keys.iter().try_for_each(|key| writeln!(out, "{key}")),
+            Self::Pairs(pairs) => pairs
+                .iter()
+                .try_for_each(|(key, value)| writeln!(out, "{key}\t{value}")),
+            Self::Exists(exists) => writeln!(out, "{exists}"),
+            Self::Count(count) => writeln!(out, "{count}"),
+            Self::Report(text, _) => writeln!(out, "{text}"),
+        }
+    }
+
+    fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        let value = match self {
+            Self::None => return Ok(()),
+            Self::Value(value) => json!(value),
+            Self::Keys(keys) => json!(keys),
+            Self::Pairs(pairs) => pairs
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value }))
+                .collect(),
+            Self::Exists(exists) => json!(exists),
+            Self::Count(count) => json!(count),
+            Self::Report(_, fields) => fields.clone(),
+        };
+
+        writeln!(out, "{value}")
+    }
+}
+
+/// Prints a failed command's error to stderr. JSON errors carry the name of
+/// the [`KvsError`] behind them and the messages of every error in the chain.
+pub fn print_error(error: &Error, format: OutputFormat) {
+    match format {
+        OutputFormat::Json => {
+            let kvs_error = kvs_error(error);
+            let report = json!({
+                "error": {
+                    "kind": kvs_error.map(KvsError::name),
+                    "code": exit_code(error),
+                    "message": error.to_string(),
+                    "chain": error.chain().map(ToString::to_string).collect::<Vec<_>>(),
+                }
+            });
+            eprintln!("{report}");
+        }
+        OutputFormat::Plain | OutputFormat::Raw => eprintln!("Error: {error:?}"),
+    }
+}
+
+/// Exits with the code documented on [`KvsError::exit_code`], or 1 for
+/// errors that did not come from the store.
+pub fn exit_code(error: &Error) -> u8 {
+    kvs_error(error).map_or(1, KvsError::exit_code)
+}
+
+pub fn exit_status(error: &Error) -> ExitCode {
+    ExitCode::from(exit_code(error))
+}
+
+fn kvs_error(error: &Error) -> Option<&KvsError> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<KvsError>())
+}
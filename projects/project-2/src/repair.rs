@@ -0,0 +1,118 @@
+use crate::{
+    log::LogRecords,
+    utils::{get_log_path, lock_dir_exclusive},
+    verify::{check_store, scan_store},
+    Problem, Result,
+};
+use serde::Serialize;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Where [`KvStore::repair`](crate::KvStore::repair) moves the files it
+/// takes out of a store.
+pub const LOST_FOUND_DIR: &str = "lost+found";
+
+/// What [`KvStore::repair`](crate::KvStore::repair) did to a store.
+#[derive(Debug, Default, Serialize)]
+pub struct RepairReport {
+    /// Keys left in the store once it was repaired.
+    pub keys: u64,
+    /// Records copied out of damaged segments.
+    pub salvaged_records: u64,
+    /// Bytes of damaged segments that could not be decoded.
+    pub lost_bytes: u64,
+    /// Files moved into `lost+found`, relative to the store directory.
+    pub quarantined: Vec<PathBuf>,
+}
+
+/// Fixes every problem [`KvStore::verify`](crate::KvStore::verify) finds in
+/// the store at `path`. A damaged segment is replaced by a fresh one under
+/// the same number, holding the records before the damage; records after it
+/// cannot be found again, as records carry no framing to resynchronise on.
+/// The damaged segment, and any file the store does not use, are moved to a
+/// new directory under `lost+found`.
+pub(crate) fn repair(path: &Path) -> Result<RepairReport> {
+    check_store(path)?;
+    let _locks = lock_dir_exclusive(path, None)?;
+
+    let found = scan_store(path)?;
+    let mut report = RepairReport::default();
+    let mut quarantine = None;
+
+    for problem in found.problems {
+        match problem {
+            Problem::CorruptRange { seq, start, end } => {
+                let log_path = get_log_path(path, seq);
+                let salvage_path = log_path.with_extension("log.tmp");
+
+                let mut salvage = File::create(&salvage_path)?;
+                io::copy(&mut File::open(&log_path)?.take(start), &mut salvage)?;
+                salvage.sync_all()?;
+                let salvaged = LogRecords::new(BufReader::new(File::open(&salvage_path)?))?
+                    .map_while(Result::ok)
+                    .count();
+
+                let name = PathBuf::from(log_path.file_name().unwrap());
+                report
+                    .quarantined
+                    .push(move_to(path, &mut quarantine, &name)?);
+                fs::rename(&salvage_path, &log_path)?;
+
+                report.salvaged_records += salvaged as u64;
+                report.lost_bytes += end - start;
+            }
+            Problem::BadSegmentName { file }
+            | Problem::OrphanFile { file }
+            | Problem::StrayFile { file } => {
+                report
+                    .quarantined
+                    .push(move_to(path, &mut quarantine, &file)?);
+            }
+            // The index is rebuilt from the segments on the next open.
+            Problem::BadPointer { .. } => {}
+        }
+    }
+    File::open(path)?.sync_all()?;
+
+    report.keys = scan_store(path)?.live_records;
+    Ok(report)
+}
+
+/// Moves `file` into this repair's directory under `lost+found`, creating it
+/// on first use, and returns where it went relative to the store.
+fn move_to(path: &Path, quarantine: &mut Option<PathBuf>, file: &Path) -> Result<PathBuf> {
+    let dir = match quarantine {
+        Some(dir) => dir,
+        None => quarantine.insert(new_quarantine_dir(path)?),
+    };
+
+    let target = dir.join(file);
+    fs::rename(path.join(file), &target)?;
+    Ok(target.strip_prefix(path).unwrap_or(&target).to_owned())
+}
+
+fn new_quarantine_dir(path: &Path) -> Result<PathBuf> {
+    let lost_found = path.join(LOST_FOUND_DIR);
+    fs::create_dir_all(&lost_found)?;
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let mut attempt = 0;
+
+    loop {
+        let name = match attempt {
+            0 => secs.to_string(),
+            _ => format!("{secs}-{attempt}"),
+        };
+        match fs::create_dir(lost_found.join(&name)) {
+            Ok(()) => return Ok(lost_found.join(name)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{output, run_kvs};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{CommandFactory, Parser};
+use project_2::{Command, KvStore, OutputFormat};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Editor, Helper,
+};
+use std::{env, fs, path::Path, path::PathBuf};
+
+const PROMPT: &str = "kvs> ";
+const HISTORY_FILE: &str = ".kvs_history";
+
+// A single line typed into the shell. Lines take the same subcommands and
+// flags as the `kvs` binary itself.
+#[derive(Debug, Parser)]
+#[command(multicall = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Runs the shell against an open store, either interactively or over the
+/// lines of a batch file.
+pub fn run(
+    store: &KvStore,
+    keyspace: &str,
+    batch: Option<&Path>,
+    format: OutputFormat,
+) -> Result<()> {
+    let shell = Shell {
+        store,
+        keyspace,
+        format,
+    };
+
+    match batch {
+        Some(path) => shell.run_batch(path),
+        None => shell.run_interactive(),
+    }
+}
+
+struct Shell<'a> {
+    store: &'a KvStore,
+    keyspace: &'a str,
+    format: OutputFormat,
+}
+
+impl Shell<'_> {
+    /// Runs every line of a script, stopping at the first command that fails.
+    fn run_batch(&self, path: &Path) -> Result<()> {
+        let script = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        for (line_number, line) in (1..).zip(script.lines()) {
+            let done = self
+                .execute(line)
+                .with_context(|| format!("{}:{line_number}: {}", path.display(), line.trim()))?;
+            if done {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_interactive(&self) -> Result<()> {
+        let mut editor = Editor::<ShellHelper, DefaultHistory>::new()?;
+        editor.set_helper(Some(ShellHelper {
+            store: self.store.clone(),
+            keyspace: self.keyspace.to_owned(),
+        }));
+
+        let history = history_path();
+        if let Some(history) = &history {
+            // There is no history yet the first time the shell is run.
+            let _ = editor.load_history(history);
+        }
+
+        loop {
+            let line = match editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            if !line.trim().is_empty() {
+                editor.add_history_entry(line.as_str())?;
+            }
+
+            match self.execute(&line) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(e) => output::print_error(&e, self.format),
+            }
+        }
+
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs one line of input, returning whether the shell should exit.
+    fn execute(&self, line: &str) -> Result<bool> {
+        let words = split_words(line)?;
+
+        match words.first().map(String::as_str) {
+            None => return Ok(false),
+            Some(word) if word.starts_with('#') => return Ok(false),
+            Some("exit" | "quit") => return Ok(true),
+            Some(_) => {}
+        }
+
+        let command = match ShellLine::try_parse_from(words) {
+            Ok(line) => line.command,
+            Err(e) if !e.use_stderr() => {
+                // Help output is not an error.
+                print!("{e}");
+                return Ok(false);
+            }
+            Err(e) => bail!(e.render().to_string().trim_end().to_owned()),
+        };
+
+        if matches!(command, Command::Shell(_)) {
+            bail!("Already in a shell");
+        }
+
+        run_kvs(self.store, self.keyspace, command, self.format)?.print(self.format)?;
+        Ok(false)
+    }
+}
+
+/// Splits a line on whitespace. Double quotes group words together, and a
+/// backslash escapes the character after it.
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars.next().ok_or_else(|| anyhow!("Trailing backslash"))?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quoted {
+        bail!("Unterminated quote");
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+}
+
+/// Completes subcommand names in the first word of a line, and keys from
+/// the index in every word after it.
+struct ShellHelper {
+    store: KvStore,
+    keyspace: String,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        // Whitespace can be more than one byte long, as with U+3000.
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(index, c)| index + c.len_utf8());
+        let word = &line[start..pos];
+
+        let candidates = match line[..start].trim().is_empty() {
+            true => ShellLine::command()
+                .get_subcommands()
+                .map(|subcommand| subcommand.get_name().to_owned())
+                .chain(["exit".to_owned()])
+                .filter(|name| name.starts_with(word))
+                .collect(),
+            false => self.store.keyspace(&self.keyspace).keys_with_prefix(word),
+        };
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
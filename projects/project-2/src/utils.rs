@@ -1,121 +1,285 @@
 use crate::{
-    log::{LogCommand, LogPointer},
-    KvsError, Result,
+    log::{Index, Keyspaces, LogCommand, LogPointer, LogRecords, DEFAULT_KEYSPACE},
+    KvsError, Result, Vfs, VfsFile,
 };
+use fs2::FileExt;
 use std::{
     collections::BTreeMap,
-    ffi::OsStr,
-    fs::{self, File, OpenOptions},
-    io::{BufReader, BufWriter, Seek, SeekFrom},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, Write},
     path::{Path, PathBuf},
+    sync::RwLock,
+    thread,
+    time::{Duration, Instant},
 };
 
+pub const LOCK_FILE: &str = "LOCK";
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
 pub fn get_log_path(path: impl AsRef<Path>, seq: u64) -> PathBuf {
     let filename = format!("{seq}.log");
     path.as_ref().join(&filename)
 }
 
-pub fn is_log_file(path: &PathBuf) -> bool {
-    path.is_file() && path.extension() == Some("log".as_ref())
+/// Where compaction writes a segment until it is complete. Only then is it
+/// renamed to its log path, so an interrupted compaction never leaves a
+/// partial segment behind for the next open to load.
+pub fn get_commit_path(path: impl AsRef<Path>, seq: u64) -> PathBuf {
+    let filename = format!("{seq}.log.compact");
+    path.as_ref().join(&filename)
 }
 
-pub fn new_log_reader(path: impl AsRef<Path>, seq: u64) -> Result<BufReader<File>> {
+pub fn new_log_reader(
+    vfs: &dyn Vfs,
+    path: impl AsRef<Path>,
+    seq: u64,
+) -> Result<BufReader<Box<dyn VfsFile>>> {
     let log_path = get_log_path(path, seq);
-    let file = File::open(log_path)?;
+    let file = vfs.open(&log_path)?;
     Ok(BufReader::new(file))
 }
 
-pub fn new_log_writer(path: impl AsRef<Path>, seq: u64) -> Result<BufWriter<File>> {
+pub fn new_log_writer(
+    vfs: &dyn Vfs,
+    path: impl AsRef<Path>,
+    seq: u64,
+) -> Result<BufWriter<Box<dyn VfsFile>>> {
     let log_path = get_log_path(path, seq);
-
-    let file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .read(true)
-        .open(log_path)
-        .map_err(KvsError::OpenFile)?;
+    let file = vfs.create(&log_path).map_err(KvsError::OpenFile)?;
 
     Ok(BufWriter::new(file))
 }
 
-pub fn new_log_pair(
-    path: impl AsRef<Path>,
-    seq: u64,
-) -> Result<(BufReader<File>, BufWriter<File>)> {
-    let path = path.as_ref();
-    let writer = new_log_writer(path, seq)?;
-    let reader = new_log_reader(path, seq)?;
-    Ok((reader, writer))
-}
-
 pub fn open_log_readers(
+    vfs: &dyn Vfs,
     path: impl AsRef<Path>,
-    seqs: &Vec<u64>,
-) -> Result<BTreeMap<u64, BufReader<File>>> {
+    seqs: &[u64],
+) -> Result<BTreeMap<u64, BufReader<Box<dyn VfsFile>>>> {
     let path = path.as_ref();
 
-    seqs.into_iter()
+    seqs.iter()
         .map(|seq| {
-            let reader = new_log_reader(path, *seq)?;
+            let reader = new_log_reader(vfs, path, *seq)?;
             Ok((*seq, reader))
         })
         .collect()
 }
 
-pub fn scan_log_seqs(path: impl AsRef<Path>) -> Result<Vec<u64>> {
-    let log_seqs = fs::read_dir(&path)?
-        .filter_map(|entry| {
-            entry.ok().filter(|e| is_log_file(&e.path())).and_then(|e| {
-                e.path()
-                    .file_name()
-                    .and_then(OsStr::to_str)
-                    .map(|s| s.trim_end_matches(".log"))
-                    .and_then(|s| s.parse().ok())
-            })
+pub fn scan_log_seqs(vfs: &dyn Vfs, path: impl AsRef<Path>) -> Result<Vec<u64>> {
+    let mut log_seqs = vfs
+        .list_files(path.as_ref())?
+        .iter()
+        .filter_map(|name| {
+            name.to_str()
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|stem| stem.parse().ok())
         })
         .collect::<Vec<_>>();
 
+    log_seqs.sort_unstable();
     Ok(log_seqs)
 }
 
-pub fn remove_log_file(path: impl AsRef<Path>, seq: u64) -> Result<()> {
+/// Takes an exclusive advisory lock on the store's LOCK file and records our
+/// PID in it. If another process holds the lock, retries until `timeout`
+/// has elapsed, then fails with `KvsError::Locked`.
+pub fn lock_dir(path: impl AsRef<Path>, timeout: Option<Duration>) -> Result<File> {
+    let lock_path = path.as_ref().join(LOCK_FILE);
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(lock_path)
+        .map_err(KvsError::OpenFile)?;
+
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => break,
+            Err(e) if e.kind() != fs2::lock_contended_error().kind() => return Err(e.into()),
+            Err(_) if deadline.is_some_and(|deadline| Instant::now() < deadline) => {
+                thread::sleep(LOCK_RETRY_INTERVAL);
+            }
+            Err(_) => {
+                let mut owner = String::new();
+                file.read_to_string(&mut owner)?;
+
+                return Err(KvsError::Locked {
+                    pid: owner.trim().parse().ok(),
+                });
+            }
+        }
+    }
+
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    file.flush()?;
+
+    Ok(file)
+}
+
+/// Takes a shared advisory lock on the store directory itself. Read-only
+/// opens hold it so that any number of readers can share a store with its
+/// writer, while maintenance that swaps files out can lock them all out.
+pub fn lock_dir_shared(path: impl AsRef<Path>, timeout: Option<Duration>) -> Result<File> {
+    lock_dir_handle(path, timeout, FileExt::try_lock_shared)
+}
+
+/// Takes the LOCK file and the directory lock both exclusively, shutting out
+/// writers and read-only handles alike while a store's files are replaced.
+pub fn lock_dir_exclusive(
+    path: impl AsRef<Path>,
+    timeout: Option<Duration>,
+) -> Result<(File, File)> {
+    let lock = lock_dir(&path, timeout)?;
+    let dir = lock_dir_handle(path, timeout, FileExt::try_lock_exclusive)?;
+    Ok((lock, dir))
+}
+
+fn lock_dir_handle(
+    path: impl AsRef<Path>,
+    timeout: Option<Duration>,
+    try_lock: fn(&File) -> std::io::Result<()>,
+) -> Result<File> {
+    let dir = File::open(path).map_err(KvsError::OpenFile)?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    loop {
+        match try_lock(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() != fs2::lock_contended_error().kind() => return Err(e.into()),
+            Err(_) if deadline.is_some_and(|deadline| Instant::now() < deadline) => {
+                thread::sleep(LOCK_RETRY_INTERVAL);
+            }
+            Err(_) => return Err(KvsError::Locked { pid: None }),
+        }
+    }
+}
+
+/// Replaces the contents of a live index with a freshly built one. New and
+/// moved keys are written before stale ones are removed, so concurrent
+/// readers never miss a key that exists in both.
+pub fn replace_index(keyspaces: &Keyspaces, fresh: Keyspaces) {
+    for fresh_index in fresh.iter() {
+        for entry in fresh_index.value().iter() {
+            let pointer = entry.value().read().unwrap().clone();
+            index_set(keyspaces, fresh_index.key(), entry.key().clone(), pointer);
+        }
+    }
+
+    for index in keyspaces.iter() {
+        let Some(fresh_index) = fresh.get(index.key()) else {
+            index.remove();
+            continue;
+        };
+
+        for entry in index.value().iter() {
+            if !fresh_index.value().contains_key(entry.key()) {
+                entry.remove();
+            }
+        }
+    }
+}
+
+pub fn remove_log_file(vfs: &dyn Vfs, path: impl AsRef<Path>, seq: u64) -> Result<()> {
     let filename = get_log_path(path, seq);
-    fs::remove_file(filename)?;
+    vfs.remove_file(&filename)?;
     Ok(())
 }
 
-pub fn build_index(
-    readers: &mut BTreeMap<u64, BufReader<File>>,
-) -> Result<(u64, BTreeMap<String, LogPointer>)> {
-    let mut index = BTreeMap::new();
+pub fn build_index<R: BufRead + Seek>(readers: &mut BTreeMap<u64, R>) -> Result<(u64, Keyspaces)> {
+    let keyspaces = Keyspaces::new();
     let mut uncompacted_bytes = 0;
 
     for (seq, reader) in readers.iter_mut() {
-        let mut offset = 0;
-        reader.seek(SeekFrom::Start(0))?;
-
-        while let Ok(command) = bincode::deserialize_from(&mut *reader) {
-            let position = reader.stream_position()?;
-            let pointer_length = position - offset;
-
-            match command {
-                LogCommand::Set(key, _) => {
-                    let pointer = LogPointer::new(*seq, offset, pointer_length);
-
-                    if let Some(prev_pointer) = index.insert(key, pointer) {
-                        uncompacted_bytes += prev_pointer.length;
-                    }
-                }
-                LogCommand::Remove(key) => {
-                    if let Some(prev_pointer) = index.remove(&key) {
-                        uncompacted_bytes += prev_pointer.length;
-                    }
-                }
-            };
-
-            offset = position;
+        uncompacted_bytes += index_segment(&keyspaces, *seq, reader)?;
+    }
+
+    Ok((uncompacted_bytes, keyspaces))
+}
+
+/// Applies the records of one segment to `keyspaces`, returning the number of
+/// bytes they made stale. Segments have to be indexed in order.
+pub fn index_segment(keyspaces: &Keyspaces, seq: u64, reader: impl BufRead + Seek) -> Result<u64> {
+    let mut uncompacted_bytes = 0;
+
+    // A torn record can only be the last one in a segment, so everything before it counts.
+    for record in LogRecords::new(reader)?.map_while(Result::ok) {
+        let pointer = LogPointer::new(seq, record.offset, record.length);
+
+        match record.command {
+            LogCommand::Set(key, _) => {
+                uncompacted_bytes += index_set(keyspaces, DEFAULT_KEYSPACE, key, pointer);
+            }
+            LogCommand::KeyspaceSet(keyspace, key, _) => {
+                uncompacted_bytes += index_set(keyspaces, &keyspace, key, pointer);
+            }
+            LogCommand::Remove(key) => {
+                uncompacted_bytes += index_remove(keyspaces, DEFAULT_KEYSPACE, &key);
+            }
+            LogCommand::KeyspaceRemove(keyspace, key) => {
+                uncompacted_bytes += index_remove(keyspaces, &keyspace, &key);
+            }
+            LogCommand::DropKeyspace(keyspace) => {
+                uncompacted_bytes += index_drop(keyspaces, &keyspace);
+            }
+        };
+    }
+
+    Ok(uncompacted_bytes)
+}
+
+/// Points `key` in `keyspace` at `pointer`, returning the length of the
+/// record it replaced, if any.
+pub fn index_set(keyspaces: &Keyspaces, keyspace: &str, key: String, pointer: LogPointer) -> u64 {
+    let entry = match keyspaces.get(keyspace) {
+        Some(entry) => entry,
+        None => keyspaces.get_or_insert_with(keyspace.to_owned(), Index::new),
+    };
+    let index = entry.value();
+
+    let replaced = match index.get(&key) {
+        Some(entry) => {
+            let mut prev = entry.value().write().unwrap();
+            std::mem::replace(&mut *prev, pointer).length
         }
+        None => {
+            index.insert(key, RwLock::new(pointer));
+            0
+        }
+    };
+
+    replaced
+}
+
+/// Removes `key` from `keyspace`, dropping the keyspace once it is empty.
+/// Returns the length of the removed record, if any.
+pub fn index_remove(keyspaces: &Keyspaces, keyspace: &str, key: &str) -> u64 {
+    let Some(entry) = keyspaces.get(keyspace) else {
+        return 0;
+    };
+    let index = entry.value();
+
+    let removed = index
+        .remove(key)
+        .map_or(0, |prev| prev.value().read().unwrap().length);
+
+    if index.is_empty() {
+        entry.remove();
     }
 
-    Ok((uncompacted_bytes, index))
+    removed
+}
+
+/// Removes a whole keyspace, returning the total length of its records.
+pub fn index_drop(keyspaces: &Keyspaces, keyspace: &str) -> u64 {
+    keyspaces.remove(keyspace).map_or(0, |entry| {
+        let index = entry.value();
+        index
+            .iter()
+            .map(|pointer| pointer.value().read().unwrap().length)
+            .sum()
+    })
 }
@@ -0,0 +1,232 @@
+use crate::{
+    backup::{REPLACED_DIR, RESTORE_DIR},
+    engines::{Engine, ENGINE_FILE},
+    log::{Keyspaces, LogCommand, LogRecords, DEFAULT_KEYSPACE},
+    repair::LOST_FOUND_DIR,
+    utils::{build_index, get_log_path, lock_dir_exclusive, open_log_readers, LOCK_FILE},
+    KvsError, OsVfs, Result,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File},
+    io::{BufRead, BufReader, Seek},
+    path::{Path, PathBuf},
+};
+
+/// What [`KvStore::verify`](crate::KvStore::verify) found in a store
+/// directory.
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub segments: u64,
+    pub records: u64,
+    pub live_records: u64,
+    pub total_bytes: u64,
+    /// Bytes no index entry points at: overwritten and removed records,
+    /// which compaction reclaims, and corrupt ranges.
+    pub unreachable_bytes: u64,
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// Bytes that do not decode as records. Decoding cannot pick up again
+    /// after them, so the range runs to the end of the segment.
+    CorruptRange { seq: u64, start: u64, end: u64 },
+    /// A log file named after something other than a segment number the
+    /// store would write.
+    BadSegmentName { file: PathBuf },
+    /// A file left behind by an interrupted compaction, restore or write.
+    OrphanFile { file: PathBuf },
+    /// A file the store never writes.
+    StrayFile { file: PathBuf },
+    /// An index entry that does not point at a set record for its key. Only
+    /// found by [`KvStore::check_index`](crate::KvStore::check_index), as
+    /// an index rebuilt from the segments matches them by construction.
+    BadPointer {
+        keyspace: String,
+        key: String,
+        seq: u64,
+        offset: u64,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CorruptRange { seq, start, end } => {
+                write!(f, "{seq}.log: bytes {start}..{end} cannot be decoded")
+            }
+            Self::BadSegmentName { file } => {
+                write!(f, "{}: not a valid segment name", file.display())
+            }
+            Self::OrphanFile { file } => write!(
+                f,
+                "{}: left behind by an interrupted compaction, restore or write",
+                file.display()
+            ),
+            Self::StrayFile { file } => {
+                write!(f, "{}: not a file the store writes", file.display())
+            }
+            Self::BadPointer {
+                keyspace,
+                key,
+                seq,
+                offset,
+            } => write!(
+                f,
+                "{keyspace}/{key}: index points at {seq}:{offset}, which is not a record for it"
+            ),
+        }
+    }
+}
+
+/// Checks every file in the store directory at `path`, which must not be
+/// open anywhere.
+pub(crate) fn verify(path: &Path) -> Result<VerifyReport> {
+    check_store(path)?;
+    let _locks = lock_dir_exclusive(path, None)?;
+
+    scan_store(path)
+}
+
+pub(crate) fn check_store(path: &Path) -> Result<()> {
+    if !path.is_dir() {
+        return Err(KvsError::StoreNotFound(path.to_owned()));
+    }
+    Engine::Kvs.check_dir(path)
+}
+
+/// Does the work of [`verify`] for a caller that holds the store's locks.
+pub(crate) fn scan_store(path: &Path) -> Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut seqs = Vec::new();
+
+    let mut names = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    names.sort();
+
+    for name in names {
+        let file = PathBuf::from(&name);
+        let Some(name) = name.to_str() else {
+            report.problems.push(Problem::StrayFile { file });
+            continue;
+        };
+
+        if name == LOCK_FILE || name == ENGINE_FILE || name == LOST_FOUND_DIR {
+            continue;
+        } else if name == RESTORE_DIR
+            || name == REPLACED_DIR
+            || name.ends_with(".log.compact")
+            || name.ends_with(".tmp")
+        {
+            report.problems.push(Problem::OrphanFile { file });
+        } else if let Some(stem) = name.strip_suffix(".log") {
+            match stem.parse::<u64>() {
+                // Anything else would be misread, or never written, by the store.
+                Ok(seq) if seq > 0 && seq.to_string() == stem && path.join(name).is_file() => {
+                    seqs.push(seq)
+                }
+                _ => report.problems.push(Problem::BadSegmentName { file }),
+            }
+        } else {
+            report.problems.push(Problem::StrayFile { file });
+        }
+    }
+    seqs.sort_unstable();
+
+    for &seq in &seqs {
+        let log_path = get_log_path(path, seq);
+        let size = fs::metadata(&log_path)?.len();
+        let mut end = 0;
+
+        for record in LogRecords::new(BufReader::new(File::open(&log_path)?))? {
+            let Ok(record) = record else {
+                report.problems.push(Problem::CorruptRange {
+                    seq,
+                    start: end,
+                    end: size,
+                });
+                break;
+            };
+
+            end = record.offset + record.length;
+            report.records += 1;
+        }
+
+        report.segments += 1;
+        report.total_bytes += size;
+    }
+
+    // The index is rebuilt from these same records, so each pointer lands on
+    // a record by construction; only what it adds up to is worth reporting.
+    let (_, index) = build_index(&mut open_log_readers(&OsVfs, path, &seqs)?)?;
+    let mut live_bytes = 0;
+
+    for keyspace in index.iter() {
+        for entry in keyspace.value().iter() {
+            live_bytes += entry.value().read().unwrap().length;
+            report.live_records += 1;
+        }
+    }
+    report.unreachable_bytes = report.total_bytes - live_bytes;
+
+    Ok(report)
+}
+
+/// The set record at each position in the log, with its length, keyspace
+/// and key.
+type SetRecords = HashMap<(u64, u64), (u64, String, String)>;
+
+/// Checks every pointer in `keyspaces` against the records decoded from
+/// `readers`, which must cover every segment the index points into.
+pub(crate) fn check_pointers<R: BufRead + Seek>(
+    keyspaces: &Keyspaces,
+    readers: BTreeMap<u64, R>,
+) -> Result<Vec<Problem>> {
+    let mut sets = SetRecords::new();
+    for (seq, reader) in readers {
+        for record in LogRecords::new(reader)?.map_while(Result::ok) {
+            let target = match record.command {
+                LogCommand::Set(key, _) => (DEFAULT_KEYSPACE.to_owned(), key),
+                LogCommand::KeyspaceSet(keyspace, key, _) => (keyspace, key),
+                _ => continue,
+            };
+            sets.insert((seq, record.offset), (record.length, target.0, target.1));
+        }
+    }
+
+    let mut problems = Vec::new();
+    for keyspace in keyspaces.iter() {
+        for entry in keyspace.value().iter() {
+            let pointer = entry.value().read().unwrap();
+            let matches = sets.get(&(pointer.file_id, pointer.offset)).is_some_and(
+                |(length, set_keyspace, set_key)| {
+                    *length == pointer.length
+                        && set_keyspace == keyspace.key()
+                        && set_key == entry.key()
+                },
+            );
+
+            if !matches {
+                problems.push(Problem::BadPointer {
+                    keyspace: keyspace.key().clone(),
+                    key: entry.key().clone(),
+                    seq: pointer.file_id,
+                    offset: pointer.offset,
+                });
+            }
+        }
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,625 @@
+use crate::{
+    utils::{lock_dir, lock_dir_shared},
+    Result,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+/// The filesystem a [`KvStore`](crate::KvStore) keeps its log in, set with
+/// [`KvStoreOptions::vfs`](crate::KvStoreOptions::vfs). Backups, archiving,
+/// `verify` and `repair` always work on the OS filesystem.
+pub trait Vfs: fmt::Debug + Send + Sync {
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Lists the names of the files, but not directories, in `path`.
+    fn list_files(&self, path: &Path) -> io::Result<Vec<OsString>>;
+
+    /// Opens an existing file for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>>;
+
+    /// Opens a file for reading and writing, creating it or truncating it.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn VfsFile>>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Locks the store directory against other processes, returning the
+    /// handle that holds the lock. Filesystems private to one process have
+    /// nothing to lock.
+    fn lock_dir(
+        &self,
+        path: &Path,
+        timeout: Option<Duration>,
+        shared: bool,
+    ) -> Result<Option<File>> {
+        let _ = (path, timeout, shared);
+        Ok(None)
+    }
+}
+
+/// A file opened through a [`Vfs`]. Positional reads take `&self`, so one
+/// handle can serve any number of concurrent readers.
+pub trait VfsFile: Read + Write + Seek + fmt::Debug + Send + Sync {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                read => {
+                    buf = &mut buf[read..];
+                    offset += read as u64;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sync_data(&self) -> io::Result<()>;
+
+    fn size(&self) -> io::Result<u64>;
+
+    /// Truncates or extends the file to `size` bytes.
+    fn set_len(&self, size: u64) -> io::Result<()>;
+
+    /// The OS file behind this one, which sealed segments can be memory
+    /// mapped from.
+    fn as_os_file(&self) -> Option<&File> {
+        None
+    }
+}
+
+/// The real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsVfs;
+
+impl Vfs for OsVfs {
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn list_files(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.path().is_file() {
+                names.push(entry.file_name());
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .read(true)
+            .open(path)?;
+
+        Ok(Box::new(file))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn lock_dir(
+        &self,
+        path: &Path,
+        timeout: Option<Duration>,
+        shared: bool,
+    ) -> Result<Option<File>> {
+        let lock = match shared {
+            true => lock_dir_shared(path, timeout)?,
+            false => lock_dir(path, timeout)?,
+        };
+
+        Ok(Some(lock))
+    }
+}
+
+impl VfsFile for File {
+    #[cfg(unix)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    /// Windows has no positional read that leaves the cursor alone, but
+    /// segments opened for reading are only ever read through this.
+    #[cfg(windows)]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        File::set_len(self, size)
+    }
+
+    fn as_os_file(&self) -> Option<&File> {
+        Some(self)
+    }
+}
+
+/// A filesystem held in memory, for stores that do not need to outlive the
+/// process. Clones share the same files.
+///
+/// Files are treated as append-only when it comes to durability: each one
+/// remembers the length it had when last synced, and
+/// [`MemoryVfs::after_crash`] cuts it back to that. Creating, renaming and
+/// removing files is durable straight away.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryVfs {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    dirs: BTreeSet<PathBuf>,
+    files: BTreeMap<PathBuf, Arc<RwLock<Inode>>>,
+}
+
+#[derive(Debug, Default)]
+struct Inode {
+    data: Vec<u8>,
+    synced_len: usize,
+}
+
+impl MemoryVfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the filesystem as it would be found after a power
+    /// failure: every file loses whatever was written since it was last
+    /// synced.
+    pub fn after_crash(&self) -> Self {
+        let state = self.state.lock().unwrap();
+        let files = state
+            .files
+            .iter()
+            .map(|(path, inode)| {
+                let inode = inode.read().unwrap();
+                let durable = Inode {
+                    data: inode.data[..inode.synced_len].to_vec(),
+                    synced_len: inode.synced_len,
+                };
+                (path.clone(), Arc::new(RwLock::new(durable)))
+            })
+            .collect();
+
+        Self {
+            state: Arc::new(Mutex::new(MemoryState {
+                dirs: state.dirs.clone(),
+                files,
+            })),
+        }
+    }
+
+    fn inode(&self, path: &Path) -> io::Result<Arc<RwLock<Inode>>> {
+        let state = self.state.lock().unwrap();
+        state
+            .files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+impl Vfs for MemoryVfs {
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.files.contains_key(path) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            state.dirs.insert(dir.to_owned());
+        }
+
+        Ok(())
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.state.lock().unwrap().dirs.contains(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let state = self.state.lock().unwrap();
+        state.dirs.contains(path) || state.files.contains_key(path)
+    }
+
+    fn list_files(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        let state = self.state.lock().unwrap();
+        if !state.dirs.contains(path) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        let names = state
+            .files
+            .keys()
+            .filter(|file| file.parent() == Some(path))
+            .filter_map(|file| file.file_name().map(ToOwned::to_owned))
+            .collect();
+
+        Ok(names)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(MemoryFile::new(self.inode(path)?)))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        let mut state = self.state.lock().unwrap();
+        let has_parent = path
+            .parent()
+            .is_none_or(|parent| parent.as_os_str().is_empty() || state.dirs.contains(parent));
+        if !has_parent {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        let inode = state.files.entry(path.to_owned()).or_default();
+        *inode.write().unwrap() = Inode::default();
+
+        Ok(Box::new(MemoryFile::new(Arc::clone(inode))))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match state.files.remove(path) {
+            Some(_) => Ok(()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let inode = state
+            .files
+            .remove(from)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        state.files.insert(to.to_owned(), inode);
+
+        Ok(())
+    }
+}
+
+/// An open handle to a [`MemoryVfs`] file, with its own cursor.
+#[derive(Debug)]
+struct MemoryFile {
+    inode: Arc<RwLock<Inode>>,
+    position: u64,
+}
+
+impl MemoryFile {
+    fn new(inode: Arc<RwLock<Inode>>) -> Self {
+        Self { inode, position: 0 }
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inode = self.inode.write().unwrap();
+        let start = self.position as usize;
+        let end = start + buf.len();
+
+        if inode.data.len() < end {
+            inode.data.resize(end, 0);
+        }
+        inode.data[start..end].copy_from_slice(buf);
+        self.position = end as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size()?.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        Ok(self.position)
+    }
+}
+
+impl VfsFile for MemoryFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let inode = self.inode.read().unwrap();
+        let start = (offset as usize).min(inode.data.len());
+        let read = buf.len().min(inode.data.len() - start);
+        buf[..read].copy_from_slice(&inode.data[start..start + read]);
+
+        Ok(read)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        let mut inode = self.inode.write().unwrap();
+        inode.synced_len = inode.data.len();
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.inode.read().unwrap().data.len() as u64)
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        let mut inode = self.inode.write().unwrap();
+        inode.data.resize(size as usize, 0);
+        inode.synced_len = inode.synced_len.min(inode.data.len());
+        Ok(())
+    }
+}
+
+/// A failure [`FaultyVfs`] can inject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The next write only writes the first half of its buffer, and reports
+    /// as much.
+    ShortWrite,
+    /// The next `fsync` fails, without making anything durable.
+    SyncFailure,
+    /// The next write fails with `ENOSPC`, writing nothing.
+    DiskFull,
+    /// The process dies: the next operation, and every one after it, fails.
+    /// [`FaultyVfs::after_crash`] then gives the filesystem a restarted
+    /// process would find.
+    Crash,
+}
+
+/// A [`MemoryVfs`] that fails on purpose, for testing how the store copes
+/// with I/O errors and recovers from crashes.
+///
+/// Faults are scheduled against a count of the operations that change the
+/// filesystem: writes, syncs, and creating, renaming and removing files.
+/// Counting the operations a workload makes with [`FaultyVfs::ops`] lets a
+/// test crash it at every point in turn.
+#[derive(Debug, Clone, Default)]
+pub struct FaultyVfs {
+    inner: MemoryVfs,
+    faults: Arc<Mutex<Faults>>,
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    ops: u64,
+    scheduled: Vec<(u64, Fault)>,
+    crashed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Write,
+    Sync,
+    Metadata,
+}
+
+impl Faults {
+    /// Counts an operation and returns the fault it should fail with, if any.
+    fn next(&mut self, op: Op) -> Option<Fault> {
+        if self.crashed {
+            return Some(Fault::Crash);
+        }
+        let index = self.ops;
+        self.ops += 1;
+
+        let position = self.scheduled.iter().position(|&(at, fault)| {
+            at <= index
+                && match fault {
+                    Fault::ShortWrite | Fault::DiskFull => op == Op::Write,
+                    Fault::SyncFailure => op == Op::Sync,
+                    Fault::Crash => true,
+                }
+        })?;
+        let (_, fault) = self.scheduled.remove(position);
+        self.crashed = fault == Fault::Crash;
+
+        Some(fault)
+    }
+}
+
+impl FaultyVfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Injects `fault` into the first operation it applies to, counting from
+    /// the `at`th operation since the filesystem was created.
+    pub fn inject(&self, fault: Fault, at: u64) {
+        self.faults.lock().unwrap().scheduled.push((at, fault));
+    }
+
+    /// The number of operations that have changed the filesystem so far.
+    pub fn ops(&self) -> u64 {
+        self.faults.lock().unwrap().ops
+    }
+
+    pub fn has_crashed(&self) -> bool {
+        self.faults.lock().unwrap().crashed
+    }
+
+    /// The filesystem as a process restarted after the crash would find it.
+    pub fn after_crash(&self) -> MemoryVfs {
+        self.inner.after_crash()
+    }
+
+    fn check(&self, op: Op) -> io::Result<()> {
+        match self.faults.lock().unwrap().next(op) {
+            None => Ok(()),
+            Some(fault) => Err(fault_error(fault)),
+        }
+    }
+
+    fn check_alive(&self) -> io::Result<()> {
+        match self.has_crashed() {
+            true => Err(fault_error(Fault::Crash)),
+            false => Ok(()),
+        }
+    }
+}
+
+fn fault_error(fault: Fault) -> io::Error {
+    match fault {
+        Fault::DiskFull => io::ErrorKind::StorageFull.into(),
+        Fault::SyncFailure => io::Error::other("injected fsync failure"),
+        Fault::ShortWrite => io::Error::other("injected short write"),
+        Fault::Crash => io::Error::other("injected crash"),
+    }
+}
+
+impl Vfs for FaultyVfs {
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check(Op::Metadata)?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.inner.is_dir(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn list_files(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        self.check_alive()?;
+        self.inner.list_files(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        self.check_alive()?;
+        Ok(Box::new(FaultyFile {
+            inner: self.inner.open(path)?,
+            vfs: self.clone(),
+        }))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        self.check(Op::Metadata)?;
+        Ok(Box::new(FaultyFile {
+            inner: self.inner.create(path)?,
+            vfs: self.clone(),
+        }))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check(Op::Metadata)?;
+        self.inner.remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(Op::Metadata)?;
+        self.inner.rename(from, to)
+    }
+}
+
+#[derive(Debug)]
+struct FaultyFile {
+    inner: Box<dyn VfsFile>,
+    vfs: FaultyVfs,
+}
+
+impl Read for FaultyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.vfs.check_alive()?;
+        self.inner.read(buf)
+    }
+}
+
+impl Write for FaultyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.vfs.faults.lock().unwrap().next(Op::Write) {
+            None => self.inner.write(buf),
+            Some(Fault::ShortWrite) => self.inner.write(&buf[..buf.len().div_ceil(2)]),
+            Some(fault) => Err(fault_error(fault)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.vfs.check_alive()?;
+        self.inner.flush()
+    }
+}
+
+impl Seek for FaultyFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.vfs.check_alive()?;
+        self.inner.seek(pos)
+    }
+}
+
+impl VfsFile for FaultyFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.vfs.check_alive()?;
+        self.inner.read_at(buf, offset)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.vfs.check(Op::Sync)?;
+        self.inner.sync_data()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.vfs.check_alive()?;
+        self.inner.size()
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        self.vfs.check(Op::Metadata)?;
+        self.inner.set_len(size)
+    }
+}
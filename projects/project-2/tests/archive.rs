@@ -0,0 +1,288 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::{contains, PredicateStrExt};
+use project_2::{log::Lsn, ArchivedSegment, KvStore, KvsError, RestorePoint};
+use std::{
+    fs::{self, File},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+use tempfile::TempDir;
+
+fn open_archived(path: &Path, archive: &Path) -> Result<KvStore> {
+    Ok(KvStore::options().archive_dir(archive).open(path)?)
+}
+
+// Restoring to an LSN should replay exactly the records written before it.
+#[test]
+fn restore_until_lsn() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let archive = temp_dir.path().join("archive");
+
+    let store = open_archived(&store_dir, &archive)?;
+    store.set("key1", "value1")?;
+    let first = store.backup_to(temp_dir.path().join("backup"))?.lsn;
+    store.set("key1", "value2")?;
+    store.set("key2", "value2")?;
+    store.remove("key1")?;
+    store.compact()?;
+    store.set("key3", "value3")?;
+    drop(store);
+    // Reopening archives the segment the last writer left behind.
+    drop(open_archived(&store_dir, &archive)?);
+
+    let restored = temp_dir.path().join("first");
+    let lsn = KvStore::restore_archive(&archive, &restored, RestorePoint::Lsn(first))?;
+    assert_eq!(lsn, first);
+    let store = KvStore::open(&restored)?;
+    assert_eq!(store.scan("")?, [("key1".to_owned(), "value1".to_owned())]);
+
+    // The start of the next segment is the same point in the log.
+    let next = Lsn {
+        seq: first.seq + 1,
+        offset: 0,
+    };
+    let restored = temp_dir.path().join("next");
+    KvStore::restore_archive(&archive, &restored, RestorePoint::Lsn(next))?;
+    assert_eq!(KvStore::open(&restored)?.scan("")?, store.scan("")?);
+
+    let restored = temp_dir.path().join("latest");
+    KvStore::restore_archive(&archive, &restored, RestorePoint::Time(u64::MAX))?;
+    let store = KvStore::open(&restored)?;
+    assert_eq!(
+        store.scan("")?,
+        [
+            ("key2".to_owned(), "value2".to_owned()),
+            ("key3".to_owned(), "value3".to_owned()),
+        ]
+    );
+
+    Ok(())
+}
+
+// A segment archived on a later open should keep the time it was last written.
+#[test]
+fn restore_until_time_uses_last_write() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let archive = temp_dir.path().join("archive");
+
+    let store = open_archived(&store_dir, &archive)?;
+    store.set("key1", "value1")?;
+    drop(store);
+
+    let written_at = UNIX_EPOCH + Duration::from_secs(1_000);
+    for entry in fs::read_dir(&store_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "log") {
+            File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(written_at)?;
+        }
+    }
+    drop(open_archived(&store_dir, &archive)?);
+
+    let result = KvStore::restore_archive(
+        &archive,
+        temp_dir.path().join("before"),
+        RestorePoint::Time(999),
+    );
+    assert!(matches!(result, Err(KvsError::RestorePointNotFound(_))));
+
+    let restored = temp_dir.path().join("at");
+    KvStore::restore_archive(&archive, &restored, RestorePoint::Time(1_000))?;
+    assert_eq!(
+        KvStore::open(&restored)?.get("key1")?,
+        Some("value1".to_owned())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unreachable_restore_points() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let archive = temp_dir.path().join("archive");
+
+    let store = open_archived(&store_dir, &archive)?;
+    store.set("key1", "value1")?;
+    let lsn = store.backup_to(temp_dir.path().join("backup"))?.lsn;
+    drop(store);
+
+    let inside_record = Lsn {
+        seq: lsn.seq,
+        offset: 1,
+    };
+    for until in [
+        RestorePoint::Lsn(inside_record),
+        RestorePoint::Lsn(Lsn {
+            seq: lsn.seq + 10,
+            offset: 0,
+        }),
+        RestorePoint::Time(0),
+    ] {
+        let result = KvStore::restore_archive(&archive, temp_dir.path().join("copy"), until);
+        assert!(matches!(result, Err(KvsError::RestorePointNotFound(_))));
+        assert!(!temp_dir.path().join("copy").exists());
+    }
+
+    let result = KvStore::restore_archive(&archive, &store_dir, RestorePoint::Lsn(lsn));
+    assert!(matches!(result, Err(KvsError::StoreExists(_))));
+
+    Ok(())
+}
+
+// A segment that fails to archive should be archived on a later rotation,
+// and compaction should not run until it has been.
+#[test]
+fn failed_archiving_is_retried() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let archive = temp_dir.path().join("archive");
+    let moved = temp_dir.path().join("moved");
+
+    let store = KvStore::options()
+        .archive_dir(&archive)
+        .max_segment_size(1)
+        .open(&store_dir)?;
+    store.set("key1", "value1")?;
+
+    // With a file in its place, copying into the archive fails.
+    fs::rename(&archive, &moved)?;
+    File::create(&archive)?;
+    store.set("key2", "value2")?;
+    assert!(store.compact().is_err());
+
+    fs::remove_file(&archive)?;
+    fs::rename(&moved, &archive)?;
+    store.set("key3", "value3")?;
+    store.compact()?;
+
+    // The aborted compaction left a gap at its commit, which the restore
+    // below has to accept.
+    let index = fs::read_to_string(archive.join("index.jsonl"))?;
+    let (reserved, archived): (Vec<_>, Vec<_>) = index
+        .lines()
+        .partition(|line| line.starts_with("{\"reserved\":"));
+    let archived = archived
+        .into_iter()
+        .map(|line| Ok(serde_json::from_str::<ArchivedSegment>(line)?.seq))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(archived[..3], [1, 2, 3]);
+    assert!(reserved.contains(&"{\"reserved\":4}"));
+    assert!(!archived.contains(&4));
+
+    let restored = temp_dir.path().join("restored");
+    KvStore::restore_archive(&archive, &restored, RestorePoint::Time(u64::MAX))?;
+    assert_eq!(KvStore::open(&restored)?.scan("")?, store.scan("")?);
+
+    Ok(())
+}
+
+// A segment missing from the middle of the archive should stop a restore
+// rather than be skipped over.
+#[test]
+fn restore_detects_missing_segments() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let archive = temp_dir.path().join("archive");
+
+    let store = KvStore::options()
+        .archive_dir(&archive)
+        .max_segment_size(1)
+        .open(&store_dir)?;
+    for key_id in 0..3 {
+        store.set(&format!("key{key_id}"), "value")?;
+    }
+    drop(store);
+
+    let index_path = archive.join("index.jsonl");
+    let index = fs::read_to_string(&index_path)?
+        .lines()
+        .filter(|line| !line.starts_with("{\"seq\":2,"))
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    fs::write(&index_path, index)?;
+    fs::remove_file(archive.join("2.log"))?;
+
+    let result = KvStore::restore_archive(
+        &archive,
+        temp_dir.path().join("restored"),
+        RestorePoint::Time(u64::MAX),
+    );
+    assert!(matches!(result, Err(KvsError::NotArchived(2))));
+
+    Ok(())
+}
+
+#[test]
+fn parse_restore_point() {
+    assert_eq!(
+        "3:120".parse(),
+        Ok(RestorePoint::Lsn(Lsn {
+            seq: 3,
+            offset: 120
+        }))
+    );
+    assert_eq!("1700000000".parse(), Ok(RestorePoint::Time(1_700_000_000)));
+    assert!("3:".parse::<RestorePoint>().is_err());
+    assert!("yesterday".parse::<RestorePoint>().is_err());
+}
+
+#[test]
+fn cli_restore_until() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let archive = temp_dir.path().join("archive");
+    let config = temp_dir.path().join("kvs.toml");
+    fs::write(&config, format!("archive_dir = {:?}\n", archive))?;
+
+    for (key, value) in [("key1", "value1"), ("key2", "value2")] {
+        kvs()
+            .args(["set", key, value, "--config"])
+            .arg(&config)
+            .arg("--dir")
+            .arg(&store_dir)
+            .assert()
+            .success();
+    }
+    // The second run archived the segment the first one wrote, but not its own.
+    kvs()
+        .args(["restore", "--until", "99999999999", "--dir"])
+        .arg(temp_dir.path().join("copy"))
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(contains("Restored archive to LSN 1:"));
+
+    kvs()
+        .args(["get", "key1", "--dir"])
+        .arg(temp_dir.path().join("copy"))
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+
+    kvs()
+        .args(["get", "key2", "--dir"])
+        .arg(temp_dir.path().join("copy"))
+        .assert()
+        .failure()
+        .code(10);
+
+    kvs()
+        .args(["restore", "--until", "yesterday", "--dir"])
+        .arg(temp_dir.path().join("other"))
+        .arg(&archive)
+        .assert()
+        .failure()
+        .code(2);
+
+    Ok(())
+}
@@ -0,0 +1,310 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::{contains, PredicateStrExt};
+use project_2::{BackupManifest, KvStore, KvsError};
+use std::{
+    fs::{self, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tempfile::TempDir;
+
+// A backup should hold exactly what was written before it, whatever happens to the store later.
+#[test]
+fn backup_and_restore() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let backup_dir = temp_dir.path().join("backup");
+
+    let store = KvStore::open(&store_dir)?;
+    for iter in 0..100 {
+        store.set("key1", &format!("value{iter}"))?;
+    }
+    store.keyspace("sessions").set("key2", "session")?;
+
+    let manifest = store.backup_to(&backup_dir)?;
+    assert_eq!(BackupManifest::read(&backup_dir)?, manifest);
+    manifest.verify(&backup_dir)?;
+
+    store.set("key1", "after backup")?;
+    store.set("key3", "after backup")?;
+    store.compact()?;
+    drop(store);
+
+    // Compaction removes the backed-up segments from the store, but not from the backup.
+    manifest.verify(&backup_dir)?;
+
+    let restored = KvStore::restore_from(&backup_dir, &store_dir)?;
+    assert_eq!(restored, manifest);
+    assert!(!store_dir.join(".replaced").exists());
+
+    let store = KvStore::open(&store_dir)?;
+    assert_eq!(store.get("key1")?, Some("value99".to_owned()));
+    assert_eq!(store.get("key3")?, None);
+    assert_eq!(
+        store.keyspace("sessions").get("key2")?,
+        Some("session".to_owned())
+    );
+
+    Ok(())
+}
+
+// Writes that race with a backup should land either wholly before or after it.
+#[test]
+fn backup_during_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path().join("store"))?;
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writer = {
+        let store = store.clone();
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            let mut key_id = 0;
+            while !done.load(Ordering::SeqCst) || key_id < 100 {
+                store.set(&format!("key{key_id:06}"), "value").unwrap();
+                key_id += 1;
+            }
+            key_id
+        })
+    };
+
+    while store.len() < 50 {
+        thread::yield_now();
+    }
+    store.backup_to(temp_dir.path().join("backup"))?;
+    done.store(true, Ordering::SeqCst);
+    let written = writer.join().unwrap();
+
+    KvStore::restore_from(temp_dir.path().join("backup"), temp_dir.path().join("copy"))?;
+    let copy = KvStore::open(temp_dir.path().join("copy"))?;
+    let keys = copy.keys();
+    assert!(!keys.is_empty() && keys.len() <= written);
+    for (key_id, key) in keys.iter().enumerate() {
+        assert_eq!(key, &format!("key{key_id:06}"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn backup_requires_empty_dir() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path().join("store"))?;
+    store.set("key1", "value1")?;
+
+    store.backup_to(temp_dir.path().join("backup"))?;
+    let result = store.backup_to(temp_dir.path().join("backup"));
+    assert!(matches!(result, Err(KvsError::BackupExists(_))));
+
+    Ok(())
+}
+
+// A corrupt backup must be rejected before the store's own files are touched.
+#[test]
+fn restore_rejects_corrupt_backup() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let backup_dir = temp_dir.path().join("backup");
+
+    let store = KvStore::open(&store_dir)?;
+    store.set("key1", "value1")?;
+    let manifest = store.backup_to(&backup_dir)?;
+    store.set("key1", "value2")?;
+
+    let result = KvStore::restore_from(&backup_dir, &store_dir);
+    assert!(matches!(result, Err(KvsError::Locked { .. })));
+    drop(store);
+
+    let segment = manifest.segments.iter().find(|s| s.size > 0).unwrap();
+    let segment_path = backup_dir.join(format!("{}.log", segment.seq));
+    // Break the link to the store's own copy before corrupting it.
+    let bytes = fs::read(&segment_path)?;
+    fs::remove_file(&segment_path)?;
+    fs::write(&segment_path, &bytes)?;
+    let mut file = OpenOptions::new().write(true).open(&segment_path)?;
+    file.seek(SeekFrom::Start(segment.size - 1))?;
+    file.write_all(b"\xff")?;
+    drop(file);
+
+    let result = KvStore::restore_from(&backup_dir, &store_dir);
+    assert!(matches!(result, Err(KvsError::ChecksumMismatch(_))));
+
+    let store = KvStore::open(&store_dir)?;
+    assert_eq!(store.get("key1")?, Some("value2".to_owned()));
+
+    Ok(())
+}
+
+// Old segments left behind by a crashed restore must never be overwritten, and
+// a restore that cannot go ahead should leave the store as it was.
+#[test]
+fn restore_keeps_replaced_segments() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let backup_dir = temp_dir.path().join("backup");
+
+    let store = KvStore::open(&store_dir)?;
+    store.set("key1", "value1")?;
+    store.backup_to(&backup_dir)?;
+    store.set("key1", "value2")?;
+    drop(store);
+
+    let replaced = store_dir.join(".replaced");
+    fs::create_dir(&replaced)?;
+    fs::write(replaced.join("1.log"), "old")?;
+    assert!(KvStore::restore_from(&backup_dir, &store_dir).is_err());
+    assert_eq!(fs::read_to_string(replaced.join("1.log"))?, "old");
+    assert!(!store_dir.join(".restore").exists());
+    assert_eq!(
+        KvStore::open(&store_dir)?.get("key1")?,
+        Some("value2".to_owned())
+    );
+
+    fs::remove_dir_all(&replaced)?;
+    KvStore::restore_from(&backup_dir, &store_dir)?;
+    assert_eq!(
+        KvStore::open(&store_dir)?.get("key1")?,
+        Some("value1".to_owned())
+    );
+
+    Ok(())
+}
+
+// Each incremental backup should restore the store exactly as it was when it was taken.
+#[test]
+fn incremental_backups() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let backups = temp_dir.path().join("backups");
+
+    let store = KvStore::open(&store_dir)?;
+    store.set("key1", "value1")?;
+    let full = store.backup_to(backups.join("full"))?;
+    assert!(!full.is_incremental());
+
+    store.set("key2", "value2")?;
+    let first = store.incremental_backup_to(backups.join("first"), &full.id)?;
+    assert_eq!(first.parent.as_ref(), Some(&full.id));
+    assert_eq!(first.inherited, [full.lsn.seq]);
+    assert_eq!(first.segments.len(), 1);
+
+    store.remove("key1")?;
+    store.compact()?;
+    store.set("key3", "value3")?;
+    let second = store.incremental_backup_to(backups.join("second"), &first.id)?;
+    assert!(second.inherited.is_empty());
+    drop(store);
+
+    KvStore::restore_from(backups.join("first"), &store_dir)?;
+    let store = KvStore::open(&store_dir)?;
+    assert_eq!(
+        store.scan("")?,
+        [
+            ("key1".to_owned(), "value1".to_owned()),
+            ("key2".to_owned(), "value2".to_owned()),
+        ]
+    );
+    drop(store);
+
+    KvStore::restore_from(backups.join("second"), &store_dir)?;
+    let store = KvStore::open(&store_dir)?;
+    assert_eq!(
+        store.scan("")?,
+        [
+            ("key2".to_owned(), "value2".to_owned()),
+            ("key3".to_owned(), "value3".to_owned()),
+        ]
+    );
+
+    let result = store.incremental_backup_to(backups.join("third"), "missing");
+    assert!(matches!(result, Err(KvsError::BackupNotFound(id)) if id == "missing"));
+    drop(store);
+
+    fs::remove_dir_all(backups.join("full"))?;
+    let result = KvStore::restore_from(backups.join("first"), &store_dir);
+    assert!(matches!(result, Err(KvsError::BackupNotFound(id)) if id == full.id));
+
+    Ok(())
+}
+
+#[test]
+fn cli_backup_and_restore() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let backup_dir = temp_dir.path().join("backup");
+    let incremental_dir = temp_dir.path().join("incremental");
+
+    kvs()
+        .args(["set", "key1", "value1", "--dir"])
+        .arg(&store_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["backup", "--dir"])
+        .arg(&store_dir)
+        .arg(&backup_dir)
+        .assert()
+        .success()
+        .stdout(contains("Backed up"));
+    let full = BackupManifest::read(&backup_dir).unwrap();
+
+    kvs()
+        .args(["set", "key2", "value2", "--dir"])
+        .arg(&store_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["backup", "--incremental", "--since", &full.id, "--dir"])
+        .arg(&store_dir)
+        .arg(&incremental_dir)
+        .assert()
+        .success()
+        .stdout(contains(format!("on top of {}", full.id)));
+
+    kvs()
+        .args(["backup", "--incremental", "--dir"])
+        .arg(&store_dir)
+        .arg(temp_dir.path().join("other"))
+        .assert()
+        .failure()
+        .code(2);
+
+    kvs()
+        .args(["rm", "key1", "--dir"])
+        .arg(&store_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["restore", "--dir"])
+        .arg(&store_dir)
+        .arg(&incremental_dir)
+        .assert()
+        .success()
+        .stdout(contains("Restored backup"));
+
+    kvs()
+        .args(["get", "key1", "--dir"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+
+    kvs()
+        .args(["get", "key2", "--dir"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(eq("value2").trim());
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use project_2::KvStore;
+use tempfile::TempDir;
+
+// Repeated reads should be served from the cache and counted in `stats()`.
+#[test]
+fn cache_hits_and_misses() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options().cache_size(1024).open(temp_dir.path())?;
+
+    store.set("key1", "value1")?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+
+    let cache = store.stats().cache.expect("cache should be enabled");
+    assert_eq!(cache.misses, 1);
+    assert_eq!(cache.hits, 2);
+    assert_eq!(cache.entries, 1);
+    assert!((cache.hit_rate() - 2.0 / 3.0).abs() < f64::EPSILON);
+
+    drop(store);
+    assert_eq!(KvStore::open(temp_dir.path())?.stats().cache, None);
+
+    Ok(())
+}
+
+// `set`, `remove` and `drop_keyspace` should never leave a stale value cached.
+#[test]
+fn cache_invalidation() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options().cache_size(1024).open(temp_dir.path())?;
+
+    store.set("key1", "value1")?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    store.set("key1", "value2")?;
+    assert_eq!(store.get("key1")?, Some("value2".to_owned()));
+    store.remove("key1")?;
+    assert_eq!(store.get("key1")?, None);
+
+    let sessions = store.keyspace("sessions");
+    sessions.set("key1", "value1")?;
+    assert_eq!(sessions.get("key1")?, Some("value1".to_owned()));
+    store.drop_keyspace("sessions")?;
+    assert_eq!(sessions.get("key1")?, None);
+    assert_eq!(store.stats().cache.unwrap().entries, 0);
+
+    Ok(())
+}
+
+// The cache should never hold more bytes than it was given.
+#[test]
+fn cache_is_bounded() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options().cache_size(100).open(temp_dir.path())?;
+
+    for key_id in 0..10 {
+        let key = format!("key{}", key_id);
+        store.set(&key, &"x".repeat(20))?;
+        store.get(&key)?;
+    }
+
+    let cache = store.stats().cache.unwrap();
+    assert!(cache.size_bytes <= 100);
+    assert_eq!(cache.entries, 3);
+
+    // Values bigger than the whole cache are never cached.
+    store.set("big", &"x".repeat(200))?;
+    store.get("big")?;
+    assert_eq!(store.stats().cache.unwrap().entries, 3);
+
+    Ok(())
+}
+
+// Compaction moves records, and cached values should follow them.
+#[test]
+fn cache_survives_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .cache_size(1024 * 1024)
+        .open(temp_dir.path())?;
+
+    store.set("stable", "value")?;
+    assert_eq!(store.get("stable")?, Some("value".to_owned()));
+
+    for iter in 0..200 {
+        for key_id in 0..100 {
+            store.set(&format!("key{}", key_id), &format!("{}", iter))?;
+        }
+    }
+
+    let before = store.stats().cache.unwrap();
+    assert_eq!(store.get("stable")?, Some("value".to_owned()));
+    assert_eq!(store.get("key42")?, Some("199".to_owned()));
+
+    let after = store.stats().cache.unwrap();
+    assert_eq!(after.hits, before.hits + 1);
+
+    Ok(())
+}
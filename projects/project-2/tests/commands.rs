@@ -0,0 +1,195 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::{contains, PredicateStrExt};
+use project_2::{KvStore, KvsError};
+use tempfile::TempDir;
+
+#[test]
+fn keys_and_scan() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    for key in ["user:2", "user:1", "session:1", "user"] {
+        store.set(key, &format!("{key}-value"))?;
+    }
+    store.keyspace("other").set("user:3", "value")?;
+
+    assert_eq!(store.keys(), ["session:1", "user", "user:1", "user:2"]);
+    assert_eq!(store.keys_with_prefix("user:"), ["user:1", "user:2"]);
+    assert!(store.keys_with_prefix("nothing").is_empty());
+    assert_eq!(
+        store.scan("user:")?,
+        [
+            ("user:1".to_owned(), "user:1-value".to_owned()),
+            ("user:2".to_owned(), "user:2-value".to_owned()),
+        ]
+    );
+
+    assert!(store.contains_key("user:1"));
+    assert!(!store.contains_key("user:3"));
+    assert_eq!(store.len(), 4);
+
+    let other = store.keyspace("other");
+    assert_eq!(other.keys(), ["user:3"]);
+    assert!(other.contains_key("user:3"));
+    assert_eq!(other.len(), 1);
+    assert!(store.keyspace("missing").is_empty());
+
+    Ok(())
+}
+
+// A dump loaded into an empty store should reproduce every keyspace.
+#[test]
+fn dump_and_load() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path().join("source"))?;
+    store.set("key1", "value\twith\nspecials")?;
+    store.set("key2", "value2")?;
+    store.keyspace("sessions").set("key1", "session1")?;
+
+    let mut dump = Vec::new();
+    assert_eq!(store.dump(&mut dump)?, 3);
+
+    let copy = KvStore::open(temp_dir.path().join("copy"))?;
+    assert_eq!(copy.load(dump.as_slice())?, 3);
+    assert_eq!(copy.get("key1")?, Some("value\twith\nspecials".to_owned()));
+    assert_eq!(copy.get("key2")?, Some("value2".to_owned()));
+    assert_eq!(
+        copy.keyspace("sessions").get("key1")?,
+        Some("session1".to_owned())
+    );
+
+    let result = copy.load("\n{\"keyspace\":\"default\"}\n".as_bytes());
+    assert!(matches!(result, Err(KvsError::Import { record: 2, .. })));
+
+    Ok(())
+}
+
+#[test]
+fn cli_listing_commands() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    for (key, value) in [("user:1", "alice"), ("user:2", "bob"), ("team:1", "red")] {
+        kvs()
+            .args(["set", key, value])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+    }
+
+    kvs()
+        .args(["keys", "--prefix", "user:"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("user:1\nuser:2\n"));
+
+    kvs()
+        .args(["scan"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("team:1\tred\nuser:1\talice\nuser:2\tbob\n"));
+
+    kvs()
+        .args(["exists", "user:1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("true").trim());
+
+    kvs()
+        .args(["exists", "user:3"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("false").trim());
+
+    kvs()
+        .args(["count"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("3").trim());
+
+    kvs()
+        .args(["stats"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("keys: 3"));
+}
+
+#[test]
+fn cli_compact() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    for iter in 0..20 {
+        kvs()
+            .args(["set", "key1", &format!("value{iter}")])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+    }
+
+    kvs()
+        .args(["compact"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("Compacted"));
+
+    kvs()
+        .args(["get", "key1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("value19").trim());
+}
+
+#[test]
+fn cli_dump_and_load() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let source = KvStore::open(temp_dir.path().join("source"))?;
+    source.set("key1", "value1")?;
+    source.keyspace("sessions").set("key2", "value2")?;
+    drop(source);
+
+    let output = kvs()
+        .args(["dump"])
+        .current_dir(temp_dir.path().join("source"))
+        .output()?;
+    assert!(output.status.success());
+
+    std::fs::create_dir(temp_dir.path().join("copy"))?;
+    kvs()
+        .args(["load"])
+        .current_dir(temp_dir.path().join("copy"))
+        .with_stdin()
+        .buffer(output.stdout)
+        .assert()
+        .success()
+        .stdout(contains("Loaded 2 keys"));
+
+    let copy = KvStore::open(temp_dir.path().join("copy"))?;
+    assert_eq!(copy.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(
+        copy.keyspace("sessions").get("key2")?,
+        Some("value2".to_owned())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cli_kvs_only_commands() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    kvs()
+        .args(["keys", "--engine", "sled"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("not supported by the sled engine"));
+}
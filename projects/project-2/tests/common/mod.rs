@@ -0,0 +1,31 @@
+// Each test binary uses only some of these.
+#![allow(dead_code)]
+
+use assert_cmd::prelude::*;
+use std::{path::Path, process::Command};
+
+/// Runs the CLI with `home` as the home directory and none of the `KVS_*`
+/// variables set, so only config and stores the test sets up are seen.
+pub fn kvs_with_home(home: &Path) -> Command {
+    let mut command = Command::cargo_bin("project-2").unwrap();
+    command
+        .env("HOME", home)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("KVS_DIR")
+        .env_remove("KVS_PROFILE");
+    command
+}
+
+/// Runs the CLI shut off from the developer's own config and environment,
+/// with a home directory that holds nothing.
+pub fn kvs() -> Command {
+    kvs_with_home(&Path::new(env!("CARGO_TARGET_TMPDIR")).join("empty-home"))
+}
+
+/// Runs `kvs-inspect` without `KVS_DIR`, so it only sees the store it is
+/// given.
+pub fn kvs_inspect() -> Command {
+    let mut command = Command::cargo_bin("kvs-inspect").unwrap();
+    command.env_remove("KVS_DIR");
+    command
+}
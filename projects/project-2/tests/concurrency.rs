@@ -0,0 +1,159 @@
+use anyhow::Result;
+use project_2::KvStore;
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+const THREADS: usize = 8;
+
+// Clones of a store should see each other's writes from any thread.
+#[test]
+fn concurrent_set_and_get() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    let handles = (0..THREADS)
+        .map(|thread_id| {
+            let store = store.clone();
+
+            thread::spawn(move || -> Result<()> {
+                for key_id in 0..200 {
+                    let key = format!("thread{}-key{}", thread_id, key_id);
+                    store.set(&key, &format!("{}", key_id))?;
+                    assert_eq!(store.get(&key)?, Some(format!("{}", key_id)));
+                }
+
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+
+    for thread_id in 0..THREADS {
+        let key = format!("thread{}-key199", thread_id);
+        assert_eq!(store.get(&key)?, Some("199".to_owned()));
+    }
+
+    // Open from disk again and check persistent data.
+    drop(store);
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("thread0-key0")?, Some("0".to_owned()));
+
+    Ok(())
+}
+
+// Readers should always observe a complete value while a writer overwrites
+// keys often enough to trigger compaction underneath them.
+#[test]
+fn reads_during_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    for key_id in 0..100 {
+        store.set(&format!("key{}", key_id), "0")?;
+    }
+
+    let writer = {
+        let store = store.clone();
+
+        thread::spawn(move || -> Result<()> {
+            for iter in 1..200 {
+                for key_id in 0..100 {
+                    store.set(&format!("key{}", key_id), &format!("{}", iter))?;
+                }
+            }
+
+            Ok(())
+        })
+    };
+
+    let readers = (0..THREADS)
+        .map(|_| {
+            let store = store.clone();
+
+            thread::spawn(move || -> Result<()> {
+                for iter in 0..200 {
+                    let key = format!("key{}", iter % 100);
+                    let value = store.get(&key)?.expect("key should never go missing");
+                    assert!(value.parse::<u32>().is_ok());
+                }
+
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    writer.join().unwrap()?;
+    for reader in readers {
+        reader.join().unwrap()?;
+    }
+
+    assert_eq!(store.get("key42")?, Some("199".to_owned()));
+
+    Ok(())
+}
+
+fn timed_reads(store: &KvStore, threads: usize, reads: usize) -> Duration {
+    let barrier = Arc::new(Barrier::new(threads + 1));
+
+    let handles = (0..threads)
+        .map(|_| {
+            let store = store.clone();
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                barrier.wait();
+                for iter in 0..reads / threads {
+                    let key = format!("key{}", iter % 1000);
+                    store.get(&key).unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    barrier.wait();
+    let start = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    start.elapsed()
+}
+
+// Reads should not serialize behind each other, so adding reader threads
+// should increase throughput with the number of available cores. The bound
+// is loose, as other tests share the machine.
+#[test]
+fn reads_scale_with_threads() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    for key_id in 0..1000 {
+        store.set(&format!("key{}", key_id), &"x".repeat(100))?;
+    }
+    // Reopened so the reads go through sealed segments.
+    drop(store);
+    let store = KvStore::open(temp_dir.path())?;
+
+    let reads = 200_000;
+    let single = timed_reads(&store, 1, reads);
+    let multi = timed_reads(&store, THREADS, reads);
+
+    let cores = thread::available_parallelism()?.get().min(4);
+    let speedup = single.as_secs_f64() / multi.as_secs_f64();
+    assert!(
+        speedup >= cores as f64 * 0.5,
+        "expected a speedup of at least {:.1}x with {} cores, got {:.2}x",
+        cores as f64 * 0.5,
+        cores,
+        speedup
+    );
+
+    Ok(())
+}
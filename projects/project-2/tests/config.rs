@@ -0,0 +1,132 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::kvs_with_home;
+use predicates::str::{contains, PredicateStrExt};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+
+fn write_config(home: &Path, toml: &str) {
+    let config_dir = home.join(".config").join("kvs");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(config_dir.join("config.toml"), toml).unwrap();
+}
+
+// `--dir` and `KVS_DIR` should both point the CLI at a store outside the current directory.
+#[test]
+fn cli_dir_and_env() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let work_dir = temp_dir.path().join("work");
+    fs::create_dir(&work_dir).unwrap();
+
+    kvs_with_home(temp_dir.path())
+        .args(["set", "key1", "value1", "--dir"])
+        .arg(&store_dir)
+        .current_dir(&work_dir)
+        .assert()
+        .success();
+
+    kvs_with_home(temp_dir.path())
+        .args(["get", "key1"])
+        .env("KVS_DIR", &store_dir)
+        .current_dir(&work_dir)
+        .assert()
+        .success()
+        .stdout(contains("value1").trim());
+
+    assert!(store_dir.join("engine").exists());
+    assert_eq!(fs::read_dir(&work_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn cli_profiles() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let main_dir = temp_dir.path().join("main");
+    let sled_dir = temp_dir.path().join("sled");
+    write_config(
+        temp_dir.path(),
+        &format!(
+            "[profiles.default]\ndir = {:?}\n\n[profiles.other]\ndir = {:?}\nengine = \"sled\"\n",
+            main_dir, sled_dir
+        ),
+    );
+
+    kvs_with_home(temp_dir.path())
+        .args(["set", "key1", "main"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    kvs_with_home(temp_dir.path())
+        .args(["set", "key1", "other", "--profile", "other"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    kvs_with_home(temp_dir.path())
+        .args(["get", "key1"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(contains("main").trim());
+
+    kvs_with_home(temp_dir.path())
+        .args(["get", "key1"])
+        .env("KVS_PROFILE", "other")
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(contains("other").trim());
+
+    // An explicit directory wins over the profile's.
+    kvs_with_home(temp_dir.path())
+        .args(["get", "key1", "--engine", "kvs", "--dir"])
+        .arg(&main_dir)
+        .env("KVS_PROFILE", "other")
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(contains("main").trim());
+
+    kvs_with_home(temp_dir.path())
+        .args(["get", "key1", "--profile", "missing"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("No profile named missing"));
+}
+
+// Writing to a directory full of unrelated files should need an explicit `--init`.
+#[test]
+fn cli_init_guard() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    fs::write(temp_dir.path().join("notes.txt"), "not a store").unwrap();
+
+    kvs_with_home(temp_dir.path())
+        .args(["set", "key1", "value1"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(contains("--init"));
+    assert!(!temp_dir.path().join("engine").exists());
+
+    kvs_with_home(temp_dir.path())
+        .args(["set", "key1", "value1", "--init"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    kvs_with_home(temp_dir.path())
+        .args(["set", "key2", "value2"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .success();
+
+    kvs_with_home(temp_dir.path())
+        .args(["get", "key2"])
+        .current_dir(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(contains("value2").trim());
+}
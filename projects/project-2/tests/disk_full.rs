@@ -0,0 +1,115 @@
+use anyhow::Result;
+use project_2::{Fault, FaultyVfs, KvStore, KvsError, SyncPolicy, Vfs};
+use std::path::Path;
+
+const STORE: &str = "/store";
+
+#[test]
+fn failed_append_is_rolled_back() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options().vfs(vfs.clone()).open(STORE)?;
+    store.set("key1", "value1")?;
+
+    // Half the record reaches the file before the disk fills up.
+    let at = vfs.ops();
+    vfs.inject(Fault::ShortWrite, at);
+    vfs.inject(Fault::DiskFull, at);
+    let result = store.set("key2", "value2");
+    assert!(matches!(result, Err(KvsError::DiskFull)));
+
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2")?, None);
+
+    store.set("key3", "value3")?;
+    drop(store);
+
+    let store = KvStore::options().vfs(vfs).open(STORE)?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2")?, None);
+    assert_eq!(store.get("key3")?, Some("value3".to_owned()));
+
+    Ok(())
+}
+
+#[test]
+fn writes_resume_once_space_frees_up() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options()
+        .vfs(vfs.clone())
+        .sync(SyncPolicy::Always)
+        .open(STORE)?;
+
+    let at = vfs.ops();
+    for _ in 0..3 {
+        vfs.inject(Fault::DiskFull, at);
+    }
+    for i in 0..3 {
+        let result = store.set(&format!("key{i}"), "value");
+        assert!(matches!(result, Err(KvsError::DiskFull)));
+    }
+    assert!(matches!(store.remove("key0"), Err(KvsError::KeyNotFound)));
+
+    for i in 0..3 {
+        store.set(&format!("key{i}"), "value")?;
+    }
+    assert_eq!(store.keys().len(), 3);
+    drop(store);
+
+    let store = KvStore::options().vfs(vfs).open(STORE)?;
+    assert_eq!(store.keys().len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn failed_compaction_leaves_store_intact() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options()
+        .vfs(vfs.clone())
+        .auto_compact(false)
+        .open(STORE)?;
+    for i in 0..10 {
+        store.set("key", &format!("value{i}"))?;
+    }
+
+    vfs.inject(Fault::DiskFull, vfs.ops());
+    assert!(matches!(store.compact(), Err(KvsError::DiskFull)));
+    assert_eq!(store.get("key")?, Some("value9".to_owned()));
+
+    store.set("other", "value")?;
+    store.compact()?;
+    drop(store);
+
+    let store = KvStore::options().vfs(vfs).open(STORE)?;
+    assert_eq!(store.get("key")?, Some("value9".to_owned()));
+    assert_eq!(store.get("other")?, Some("value".to_owned()));
+
+    Ok(())
+}
+
+#[test]
+fn failed_rotation_keeps_the_record() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options()
+        .vfs(vfs.clone())
+        .sync(SyncPolicy::Every(1000))
+        .max_segment_size(10)
+        .open(STORE)?;
+
+    // The record is written without a sync, so the only one is the rotation's.
+    vfs.inject(Fault::SyncFailure, vfs.ops());
+    store.set("key1", "value1")?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert!(!vfs.exists(&Path::new(STORE).join("2.log")));
+
+    // The next append rotates first.
+    store.set("key2", "value2")?;
+    assert!(vfs.exists(&Path::new(STORE).join("2.log")));
+    drop(store);
+
+    let store = KvStore::options().vfs(vfs).open(STORE)?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2")?, Some("value2".to_owned()));
+
+    Ok(())
+}
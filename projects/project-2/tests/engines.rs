@@ -0,0 +1,127 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::{contains, PredicateStrExt};
+use project_2::{
+    engines::{MemoryKvsEngine, SledKvsEngine},
+    Engine, KvStore, KvsEngine, KvsError,
+};
+use tempfile::TempDir;
+
+fn exercise_engine(engine: &impl KvsEngine) -> Result<()> {
+    engine.set("key1", "value1")?;
+    engine.set("key2", "value2")?;
+    engine.set("key1", "value3")?;
+
+    assert_eq!(engine.get("key1")?, Some("value3".to_owned()));
+    assert_eq!(engine.get("key2")?, Some("value2".to_owned()));
+    assert_eq!(engine.get("key3")?, None);
+
+    engine.remove("key2")?;
+    assert_eq!(engine.get("key2")?, None);
+    assert!(matches!(engine.remove("key2"), Err(KvsError::KeyNotFound)));
+
+    Ok(())
+}
+
+// Every engine should behave the same through the `KvsEngine` trait.
+#[test]
+fn engines_share_behaviour() -> Result<()> {
+    let kvs_dir = TempDir::new().expect("unable to create temporary working directory");
+    let sled_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    exercise_engine(&KvStore::open(kvs_dir.path())?)?;
+    exercise_engine(&SledKvsEngine::open(sled_dir.path())?)?;
+    exercise_engine(&MemoryKvsEngine::new())?;
+
+    Ok(())
+}
+
+// Sled data should persist between opens.
+#[test]
+fn sled_persists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let engine = SledKvsEngine::open(temp_dir.path())?;
+    engine.set("key1", "value1")?;
+    drop(engine);
+
+    let engine = SledKvsEngine::open(temp_dir.path())?;
+    assert_eq!(engine.get("key1")?, Some("value1".to_owned()));
+
+    Ok(())
+}
+
+// Opening a data directory with a different engine should be refused.
+#[test]
+fn wrong_engine_is_refused() -> Result<()> {
+    let kvs_dir = TempDir::new().expect("unable to create temporary working directory");
+    let sled_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    drop(KvStore::open(kvs_dir.path())?);
+    drop(SledKvsEngine::open(sled_dir.path())?);
+
+    assert!(matches!(
+        SledKvsEngine::open(kvs_dir.path()),
+        Err(KvsError::WrongEngine { .. })
+    ));
+    assert!(matches!(
+        KvStore::open(sled_dir.path()),
+        Err(KvsError::WrongEngine { .. })
+    ));
+
+    Ok(())
+}
+
+// Directories with log files but no engine record belong to `kvs`.
+#[test]
+fn unrecorded_log_dir_belongs_to_kvs() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    drop(KvStore::open(temp_dir.path())?);
+    std::fs::remove_file(temp_dir.path().join("engine"))?;
+
+    assert!(matches!(
+        Engine::Sled.claim_dir(temp_dir.path()),
+        Err(KvsError::WrongEngine { .. })
+    ));
+    Engine::Kvs.claim_dir(temp_dir.path())?;
+
+    Ok(())
+}
+
+// `kvs --engine <ENGINE>` should select the engine, and refuse to switch engines.
+#[test]
+fn cli_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    kvs()
+        .args(["--engine", "sled", "set", "key1", "value1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["get", "key1", "--engine", "sled"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+
+    kvs()
+        .args(["get", "key1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("sled engine"));
+
+    kvs()
+        .args(["--engine", "sled", "--keyspace", "sessions", "get", "key1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("not supported"));
+}
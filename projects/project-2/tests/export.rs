@@ -0,0 +1,155 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::str::contains;
+use project_2::{Format, KvStore, KvsError, OnConflict};
+use std::{fs, io::Cursor};
+use tempfile::TempDir;
+
+const FORMATS: [Format; 4] = [Format::JsonLines, Format::Ron, Format::Bson, Format::Csv];
+
+fn seed(store: &KvStore) -> Result<()> {
+    store.set("plain", "value")?;
+    store.set("tricky", "comma, \"quotes\"\nnewline\tand ünïcode")?;
+    store.set("empty", "")?;
+    store.keyspace("sessions").set("plain", "session")?;
+    Ok(())
+}
+
+// Every format should carry every keyspace and awkward values through unchanged.
+#[test]
+fn export_import_round_trip() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let source = KvStore::open(temp_dir.path().join("source"))?;
+    seed(&source)?;
+
+    for format in FORMATS {
+        let mut exported = Vec::new();
+        assert_eq!(source.export(&mut exported, format)?, 4);
+
+        let copy = KvStore::open(temp_dir.path().join(format!("{format:?}")))?;
+        assert_eq!(
+            copy.import(Cursor::new(exported), format, OnConflict::Fail)?,
+            4
+        );
+        assert_eq!(copy.scan("")?, source.scan("")?);
+        assert_eq!(
+            copy.keyspace("sessions").get("plain")?,
+            Some("session".to_owned())
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn import_conflicts() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("b", "old")?;
+
+    let input = "{\"keyspace\":\"default\",\"key\":\"a\",\"value\":\"new\"}\n\
+                 {\"keyspace\":\"default\",\"key\":\"b\",\"value\":\"new\"}\n";
+
+    let result = store.import(Cursor::new(input), Format::JsonLines, OnConflict::Fail);
+    assert!(matches!(result, Err(KvsError::Conflict { key, .. }) if key == "b"));
+    assert_eq!(store.get("a")?, None);
+    assert_eq!(store.get("b")?, Some("old".to_owned()));
+
+    let count = store.import(Cursor::new(input), Format::JsonLines, OnConflict::Overwrite)?;
+    assert_eq!(count, 2);
+    assert_eq!(store.get("b")?, Some("new".to_owned()));
+
+    // A key that appears twice conflicts with itself.
+    let input = "{\"keyspace\":\"default\",\"key\":\"c\",\"value\":\"1\"}\n\
+                 {\"keyspace\":\"default\",\"key\":\"c\",\"value\":\"2\"}\n";
+    let result = store.import(Cursor::new(input), Format::JsonLines, OnConflict::Fail);
+    assert!(matches!(result, Err(KvsError::Conflict { key, .. }) if key == "c"));
+    assert_eq!(store.get("c")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn import_malformed_records() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    let csv = "keyspace,key,value\ndefault,a,1\ndefault,b\n";
+    let result = store.import(Cursor::new(csv), Format::Csv, OnConflict::Overwrite);
+    assert!(matches!(result, Err(KvsError::Import { record: 2, .. })));
+
+    let result = store.import_stream(&[1, 2, 3][..], Format::Bson);
+    assert!(matches!(result, Err(KvsError::Import { record: 1, .. })));
+
+    let ron = "(keyspace: \"default\", key: \"c\", value: \"3\")\nnot ron\n";
+    let result = store.import(Cursor::new(ron), Format::Ron, OnConflict::Overwrite);
+    assert!(matches!(result, Err(KvsError::Import { record: 2, .. })));
+    assert_eq!(store.get("c")?, Some("3".to_owned()));
+
+    // Checked before anything is applied, so nothing lands.
+    let ron = "(keyspace: \"default\", key: \"d\", value: \"4\")\nnot ron\n";
+    let result = store.import(Cursor::new(ron), Format::Ron, OnConflict::Fail);
+    assert!(matches!(result, Err(KvsError::Import { record: 2, .. })));
+    assert_eq!(store.get("d")?, None);
+
+    Ok(())
+}
+
+// The CLI should pick the format from the file extension unless told otherwise.
+#[test]
+fn cli_export_import() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let source_dir = temp_dir.path().join("source");
+    let copy_dir = temp_dir.path().join("copy");
+    seed(&KvStore::open(&source_dir)?)?;
+    let file = temp_dir.path().join("export.csv");
+
+    kvs()
+        .args(["export", "--dir"])
+        .arg(&source_dir)
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(contains("Exported 4 keys"));
+    assert!(fs::read_to_string(&file)?.starts_with("keyspace,key,value\n"));
+
+    kvs()
+        .args(["import", "--dir"])
+        .arg(&copy_dir)
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(contains("Imported 4 keys"));
+
+    kvs()
+        .args(["import", "--fail-on-conflict", "--dir"])
+        .arg(&copy_dir)
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(contains("already exists"));
+
+    kvs()
+        .args(["import", "--fail-on-conflict", "--format", "csv", "--dir"])
+        .arg(&copy_dir)
+        .with_stdin()
+        .path(&file)?
+        .assert()
+        .failure()
+        .stderr(contains("already exists"));
+
+    let output = kvs()
+        .args(["export", "--format", "ron", "--dir"])
+        .arg(&source_dir)
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.starts_with("(keyspace:"));
+
+    let copy = KvStore::open(&copy_dir)?;
+    assert_eq!(copy.scan("")?, KvStore::open(&source_dir)?.scan("")?);
+
+    Ok(())
+}
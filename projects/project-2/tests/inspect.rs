@@ -0,0 +1,98 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs_inspect;
+use predicates::str::contains;
+use project_2::KvStore;
+use serde_json::Value;
+use std::{fs::OpenOptions, io::Write};
+use tempfile::TempDir;
+
+fn inspect(args: &[&str], dir: &TempDir) -> Result<Value> {
+    let output = kvs_inspect()
+        .args(args)
+        .arg("--json")
+        .arg(dir.path())
+        .output()?;
+    assert!(output.status.success());
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+// Each record should be decoded, and only the ones the index points at marked live.
+#[test]
+fn records_and_liveness() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    store.set("key1", &"x".repeat(100))?;
+    store.set("key2", "value2")?;
+    store.remove("key2")?;
+    store.keyspace("sessions").set("key1", "session")?;
+    drop(store);
+
+    let report = inspect(&[], &temp_dir)?;
+    let segments = report["segments"].as_array().unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0]["records"], 5);
+    assert_eq!(segments[0]["live"], 2);
+
+    let entries = segments[0]["entries"].as_array().unwrap();
+    let summary: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry["command"].as_str().unwrap(),
+                entry["keyspace"].as_str().unwrap(),
+                entry["live"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("set", "default", false),
+            ("set", "default", true),
+            ("set", "default", false),
+            ("remove", "default", false),
+            ("set", "sessions", true),
+        ]
+    );
+    assert_eq!(entries[1]["value"], format!("{}…", "x".repeat(32)));
+    assert_eq!(entries[1]["value_bytes"], 100);
+    assert_eq!(entries[1]["offset"], entries[0]["length"]);
+
+    let report = inspect(&["--value-width", "0", "--summary"], &temp_dir)?;
+    assert!(report["segments"][0].get("entries").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn torn_record() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    drop(store);
+
+    let mut log = OpenOptions::new()
+        .append(true)
+        .open(temp_dir.path().join("1.log"))?;
+    log.write_all(&[0, 0, 0])?;
+
+    let report = inspect(&["--segment", "1"], &temp_dir)?;
+    assert_eq!(report["segments"][0]["records"], 1);
+    assert_eq!(
+        report["segments"][0]["error"]["offset"],
+        report["segments"][0]["entries"][0]["length"]
+    );
+
+    kvs_inspect()
+        .arg(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(contains("live set"))
+        .stdout(contains("undecodable"));
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::{contains, PredicateStrExt};
+use project_2::{KvStore, KvsError};
+use tempfile::TempDir;
+
+// Keys in different keyspaces should not collide, and should survive a reopen.
+#[test]
+fn keyspaces_are_isolated() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    store.set("key1", "default")?;
+    store.keyspace("sessions").set("key1", "sessions")?;
+    store.keyspace("flags").set("key1", "flags")?;
+
+    assert_eq!(store.get("key1")?, Some("default".to_owned()));
+    assert_eq!(
+        store.keyspace("sessions").get("key1")?,
+        Some("sessions".to_owned())
+    );
+    assert_eq!(
+        store.keyspace("flags").get("key1")?,
+        Some("flags".to_owned())
+    );
+    assert_eq!(store.keyspace("config").get("key1")?, None);
+
+    store.keyspace("flags").remove("key1")?;
+    assert_eq!(store.keyspace("flags").get("key1")?, None);
+    assert_eq!(store.get("key1")?, Some("default".to_owned()));
+
+    // Open from disk again and check persistent data.
+    drop(store);
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("key1")?, Some("default".to_owned()));
+    assert_eq!(
+        store.keyspace("sessions").get("key1")?,
+        Some("sessions".to_owned())
+    );
+    assert_eq!(store.keyspace("flags").get("key1")?, None);
+    assert_eq!(store.keyspaces(), vec!["default", "sessions"]);
+
+    Ok(())
+}
+
+// Dropping a keyspace should remove all of its keys and leave the others alone.
+#[test]
+fn drop_keyspace() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    for key_id in 0..100 {
+        let key = format!("key{}", key_id);
+        store.keyspace("sessions").set(&key, "value")?;
+        store.keyspace("config").set(&key, "value")?;
+    }
+
+    store.drop_keyspace("sessions")?;
+    assert_eq!(store.keyspace("sessions").get("key1")?, None);
+    assert_eq!(
+        store.keyspace("config").get("key1")?,
+        Some("value".to_owned())
+    );
+    assert!(matches!(
+        store.drop_keyspace("sessions"),
+        Err(KvsError::KeyspaceNotFound)
+    ));
+
+    // A dropped keyspace can be reused, and only the new keys come back.
+    store.keyspace("sessions").set("key2", "new")?;
+
+    drop(store);
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.keyspace("sessions").get("key1")?, None);
+    assert_eq!(
+        store.keyspace("sessions").get("key2")?,
+        Some("new".to_owned())
+    );
+    assert_eq!(
+        store.keyspace("config").get("key99")?,
+        Some("value".to_owned())
+    );
+
+    Ok(())
+}
+
+// Compaction should carry keys from every keyspace into the new log.
+#[test]
+fn compaction_keeps_keyspaces() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    store.keyspace("config").set("stable", "value")?;
+
+    for iter in 0..200 {
+        for key_id in 0..100 {
+            let key = format!("key{}", key_id);
+            store.keyspace("sessions").set(&key, &format!("{}", iter))?;
+        }
+    }
+
+    drop(store);
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(
+        store.keyspace("config").get("stable")?,
+        Some("value".to_owned())
+    );
+    assert_eq!(
+        store.keyspace("sessions").get("key42")?,
+        Some("199".to_owned())
+    );
+
+    Ok(())
+}
+
+// `kvs --keyspace <NAME>` should scope set, get and rm to that keyspace.
+#[test]
+fn cli_keyspace() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    kvs()
+        .args(["--keyspace", "sessions", "set", "key1", "value1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["get", "key1", "--keyspace", "sessions"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+
+    kvs()
+        .args(["get", "key1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("Key not found"));
+
+    kvs()
+        .args(["keyspaces"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("sessions").trim());
+
+    kvs()
+        .args(["drop-keyspace", "sessions"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["--keyspace", "sessions", "get", "key1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("Key not found"));
+}
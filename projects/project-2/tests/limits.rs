@@ -0,0 +1,68 @@
+use anyhow::Result;
+use project_2::{KvStore, KvsError};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn size_limits() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .max_key_size(8)
+        .max_value_size(16)
+        .open(temp_dir.path())?;
+
+    store.set("12345678", &"v".repeat(16))?;
+    assert!(matches!(store.set("", "value"), Err(KvsError::EmptyKey)));
+    assert!(matches!(
+        store.set("123456789", "value"),
+        Err(KvsError::KeyTooLarge { size: 9, max: 8 })
+    ));
+    assert!(matches!(
+        store.keyspace("other").set("key", &"v".repeat(17)),
+        Err(KvsError::ValueTooLarge { size: 17, max: 16 })
+    ));
+    assert!(matches!(
+        store.keyspace("123456789").set("key", "value"),
+        Err(KvsError::KeyspaceNameTooLarge { size: 9, max: 8 })
+    ));
+    drop(store);
+
+    // Rejected writes never reached the log.
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.keys(), ["12345678"]);
+    assert_eq!(store.keyspaces(), ["default"]);
+
+    Ok(())
+}
+
+#[test]
+fn default_limits() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    store.set(&"k".repeat(64 * 1024), "value")?;
+    assert!(matches!(
+        store.set(&"k".repeat(64 * 1024 + 1), "value"),
+        Err(KvsError::KeyTooLarge { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn corrupt_length_prefix() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    KvStore::open(temp_dir.path())?.set("key1", "value1")?;
+
+    // A `Set` whose key claims to be a terabyte long.
+    let mut record = 0u32.to_le_bytes().to_vec();
+    record.extend((1u64 << 40).to_le_bytes());
+    record.extend(b"key2");
+    fs::write(temp_dir.path().join("2.log"), record)?;
+
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2")?, None);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::str::contains;
+use project_2::{KvStore, KvsError};
+use std::{thread, time::Duration};
+use tempfile::TempDir;
+
+// A second open of a store that is already open should be refused, naming
+// the process that holds it.
+#[test]
+fn second_writer_is_locked_out() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    match KvStore::open(temp_dir.path()) {
+        Err(KvsError::Locked { pid }) => assert_eq!(pid, Some(std::process::id())),
+        other => panic!("expected the store to be locked, got {:?}", other),
+    }
+
+    // Clones share the lock, which is released once the last one is dropped.
+    let clone = store.clone();
+    drop(store);
+    assert!(KvStore::open(temp_dir.path()).is_err());
+    drop(clone);
+    KvStore::open(temp_dir.path())?;
+
+    Ok(())
+}
+
+// With a lock timeout, opening should wait for the current owner to finish.
+#[test]
+fn lock_timeout_waits_for_release() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+
+    let owner = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        drop(store);
+    });
+
+    let store = KvStore::options()
+        .lock_timeout(Duration::from_secs(5))
+        .open(temp_dir.path())?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    owner.join().unwrap();
+
+    let result = KvStore::options()
+        .lock_timeout(Duration::from_millis(100))
+        .open(temp_dir.path());
+    assert!(matches!(result, Err(KvsError::Locked { .. })));
+
+    Ok(())
+}
+
+// `kvs` should fail cleanly while another process owns the store.
+#[test]
+fn cli_locked() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+
+    kvs()
+        .args(["set", "key1", "value1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("locked by another process"));
+
+    kvs()
+        .args(["--wait", "1", "set", "key1", "value1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains(format!("pid {}", std::process::id())));
+
+    drop(store);
+
+    kvs()
+        .args(["--wait", "1", "set", "key1", "value1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Ok(())
+}
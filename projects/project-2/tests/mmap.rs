@@ -0,0 +1,56 @@
+use anyhow::Result;
+use project_2::KvStore;
+use tempfile::TempDir;
+
+// Memory-mapped reads should return the same data as the file-based path,
+// both for sealed segments and for the active one.
+#[test]
+fn mmap_reads() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    store.set("key2", "value2")?;
+    drop(store);
+
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert_eq!(store.stats().mapped_segments, 0);
+    drop(store);
+
+    let store = KvStore::options().mmap(true).open(temp_dir.path())?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+    assert!(store.stats().mapped_segments > 0);
+
+    // Written to the active segment, which is never mapped.
+    store.set("key2", "value3")?;
+    assert_eq!(store.get("key2")?, Some("value3".to_owned()));
+    assert_eq!(store.get("key3")?, None);
+
+    Ok(())
+}
+
+// Compaction should seal its output, which mapped reads then pick up.
+#[test]
+fn mmap_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options().mmap(true).open(temp_dir.path())?;
+
+    for iter in 0..200 {
+        for key_id in 0..100 {
+            let key = format!("key{}", key_id);
+            store.set(&key, &format!("{}", iter))?;
+        }
+    }
+
+    for key_id in 0..100 {
+        let key = format!("key{}", key_id);
+        assert_eq!(store.get(&key)?, Some("199".to_owned()));
+    }
+
+    drop(store);
+    let store = KvStore::options().mmap(true).open(temp_dir.path())?;
+    assert_eq!(store.get("key42")?, Some("199".to_owned()));
+    assert!(store.stats().mapped_segments > 0);
+
+    Ok(())
+}
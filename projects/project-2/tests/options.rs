@@ -0,0 +1,187 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::str::{contains, PredicateStrExt};
+use project_2::{KvStore, KvStoreOptions, KvsError, SyncPolicy};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+
+fn log_files(path: &Path) -> usize {
+    fs::read_dir(path)
+        .unwrap()
+        .filter(|entry| {
+            let path = entry.as_ref().unwrap().path();
+            path.extension() == Some("log".as_ref())
+        })
+        .count()
+}
+
+// Without create_if_missing, opening an empty location should fail and leave nothing behind.
+#[test]
+fn create_if_missing() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path().join("store");
+
+    let result = KvStore::options().create_if_missing(false).open(&path);
+    assert!(matches!(result, Err(KvsError::StoreNotFound(_))));
+    assert!(!path.exists());
+
+    KvStore::open(&path)?.set("key1", "value1")?;
+    let store = KvStore::options().create_if_missing(false).open(&path)?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+
+    Ok(())
+}
+
+#[test]
+fn error_if_exists() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let store = KvStore::options()
+        .error_if_exists(true)
+        .open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    drop(store);
+
+    let result = KvStore::options()
+        .error_if_exists(true)
+        .open(temp_dir.path());
+    assert!(matches!(result, Err(KvsError::StoreExists(_))));
+
+    Ok(())
+}
+
+// With auto_compact off, stale records should only go away on an explicit compact.
+#[test]
+fn manual_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .auto_compact(false)
+        .compaction_threshold(1)
+        .open(temp_dir.path())?;
+
+    for iter in 0..100 {
+        store.set("key1", &format!("value{iter}"))?;
+    }
+    assert!(store.stats().uncompacted_bytes > 0);
+
+    store.compact()?;
+    assert_eq!(store.stats().uncompacted_bytes, 0);
+    assert_eq!(store.get("key1")?, Some("value99".to_owned()));
+    drop(store);
+
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("key1")?, Some("value99".to_owned()));
+    assert_eq!(store.stats().uncompacted_bytes, 0);
+
+    Ok(())
+}
+
+// Writes past max_segment_size should roll over to new segments that still read back.
+#[test]
+fn max_segment_size() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .max_segment_size(256)
+        .sync(SyncPolicy::Every(10))
+        .open(temp_dir.path())?;
+
+    for key_id in 0..100 {
+        store.set(&format!("key{key_id}"), &format!("value{key_id}"))?;
+    }
+    assert!(log_files(temp_dir.path()) > 5);
+
+    for key_id in 0..100 {
+        assert_eq!(
+            store.get(&format!("key{key_id}"))?,
+            Some(format!("value{key_id}"))
+        );
+    }
+    drop(store);
+
+    let store = KvStore::open(temp_dir.path())?;
+    for key_id in 0..100 {
+        assert_eq!(
+            store.get(&format!("key{key_id}"))?,
+            Some(format!("value{key_id}"))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn options_from_toml() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = KvStoreOptions::from_toml(
+        r#"
+        create_if_missing = false
+        sync = "always"
+        cache_size = 4096
+        lock_timeout = 0.5
+        "#,
+    )?;
+    assert!(matches!(
+        options.open(temp_dir.path().join("store")),
+        Err(KvsError::StoreNotFound(_))
+    ));
+
+    let options = KvStoreOptions::from_toml("sync = { every = 8 }\nmax_segment_size = 1024")?;
+    let store = options.open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+
+    assert!(matches!(
+        KvStoreOptions::from_toml("compaction_treshold = 10"),
+        Err(KvsError::Config(_))
+    ));
+
+    Ok(())
+}
+
+// The CLI should open the store with the options from --config.
+#[test]
+fn cli_config_file() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config = temp_dir.path().join("kvs.toml");
+    fs::write(&config, "create_if_missing = false\n")?;
+    let store_dir = temp_dir.path().join("store");
+    fs::create_dir(&store_dir)?;
+
+    kvs()
+        .args(["set", "key1", "value1", "--config"])
+        .arg(&config)
+        .current_dir(&store_dir)
+        .assert()
+        .failure()
+        .stderr(contains("No store exists"));
+
+    fs::write(&config, "sync = \"always\"\n")?;
+    kvs()
+        .args(["set", "key1", "value1", "--config"])
+        .arg(&config)
+        .current_dir(&store_dir)
+        .assert()
+        .success();
+
+    kvs()
+        .args(["get", "key1", "--config"])
+        .arg(&config)
+        .current_dir(&store_dir)
+        .assert()
+        .success()
+        .stdout(contains("value1").trim());
+
+    fs::write(&config, "no_such_option = true\n")?;
+    kvs()
+        .args(["get", "key1", "--config"])
+        .arg(&config)
+        .current_dir(&store_dir)
+        .assert()
+        .failure()
+        .stderr(contains("Invalid configuration"));
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use predicates::ord::eq;
+use predicates::str::contains;
+use project_2::KvsError;
+use serde_json::{json, Value};
+use std::process::Command;
+use tempfile::TempDir;
+
+fn kvs(temp_dir: &TempDir) -> Command {
+    let mut command = common::kvs();
+    command.current_dir(temp_dir);
+    command
+}
+
+#[test]
+fn cli_output_formats() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    kvs(&temp_dir)
+        .args(["set", "key1", "value1"])
+        .assert()
+        .success();
+    kvs(&temp_dir)
+        .args(["set", "key2", "value\"2"])
+        .assert()
+        .success();
+
+    kvs(&temp_dir)
+        .args(["get", "key1", "--output", "raw"])
+        .assert()
+        .success()
+        .stdout(eq("value1"));
+
+    kvs(&temp_dir)
+        .args(["get", "key2", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(eq("\"value\\\"2\"\n"));
+
+    kvs(&temp_dir)
+        .args(["keys", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(eq("[\"key1\",\"key2\"]\n"));
+
+    kvs(&temp_dir)
+        .args(["scan", "--prefix", "key1", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(eq("[{\"key\":\"key1\",\"value\":\"value1\"}]\n"));
+
+    kvs(&temp_dir)
+        .args(["exists", "key3", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(eq("false\n"));
+
+    kvs(&temp_dir)
+        .args(["stats", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(contains("\"keys\":2"));
+}
+
+// Each store error should exit with its own code, and JSON errors should carry the chain.
+#[test]
+fn cli_exit_codes() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    kvs(&temp_dir)
+        .args(["get", "key1"])
+        .assert()
+        .code(i32::from(KvsError::KeyNotFound.exit_code()))
+        .stderr(contains("Key not found"));
+
+    let output = kvs(&temp_dir)
+        .args(["rm", "key1", "--output", "json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(10));
+    let error: Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(
+        error,
+        json!({
+            "error": {
+                "kind": "KeyNotFound",
+                "code": 10,
+                "message": "Key not found",
+                "chain": ["Key not found"],
+            }
+        })
+    );
+
+    kvs(&temp_dir)
+        .args(["drop-keyspace", "missing"])
+        .assert()
+        .code(i32::from(KvsError::KeyspaceNotFound.exit_code()));
+
+    kvs(&temp_dir)
+        .args(["shell", "--batch", "missing-script", "--output", "json"])
+        .assert()
+        .code(1)
+        .stderr(contains("\"kind\":null"))
+        .stderr(contains("Failed to read missing-script"));
+
+    kvs(&temp_dir)
+        .args([
+            "set",
+            "key1",
+            "value1",
+            "--engine",
+            "sled",
+            "--keyspace",
+            "other",
+        ])
+        .assert()
+        .code(18);
+
+    kvs(&temp_dir).args(["no-such-command"]).assert().code(2);
+}
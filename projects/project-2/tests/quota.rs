@@ -0,0 +1,78 @@
+use anyhow::Result;
+use project_2::{KvStore, KvsError};
+use tempfile::TempDir;
+
+const QUOTA: u64 = 1024;
+
+#[test]
+fn quota_stops_new_data() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .max_disk_size(QUOTA)
+        .auto_compact(false)
+        .open(temp_dir.path())?;
+
+    let value = "x".repeat(100);
+    let mut written = 0;
+    let result = loop {
+        match store.set(&format!("key{written}"), &value) {
+            Ok(()) => written += 1,
+            Err(e) => break e,
+        }
+    };
+    assert!(matches!(
+        result,
+        KvsError::QuotaExceeded { quota: QUOTA, .. }
+    ));
+    assert!(written > 0);
+    assert!(store.disk_size()? <= QUOTA);
+
+    // Reads and removes keep working, and compacting the removes frees space again.
+    assert_eq!(store.get("key0")?, Some(value.clone()));
+    store.remove("key0")?;
+    store.remove("key1")?;
+    store.set("new", &value)?;
+    assert_eq!(store.get("new")?, Some(value));
+    assert_eq!(store.get("key0")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn overwrites_compact_instead_of_failing() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .max_disk_size(QUOTA)
+        .auto_compact(false)
+        .open(temp_dir.path())?;
+
+    for i in 0..100 {
+        store.set("key", &format!("{i:0>100}"))?;
+    }
+    assert_eq!(store.get("key")?, Some(format!("{:0>100}", 99)));
+    assert!(store.disk_size()? <= QUOTA);
+
+    Ok(())
+}
+
+#[test]
+fn stats_report_usage() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .max_disk_size(QUOTA)
+        .open(temp_dir.path())?;
+    store.set("key", "value")?;
+    store.remove("key")?;
+
+    let stats = store.stats();
+    assert_eq!(stats.disk_quota, Some(QUOTA));
+    assert_eq!(stats.disk_bytes, store.disk_size()?);
+    assert!(stats.disk_bytes > 0);
+    drop(store);
+
+    let stats = KvStore::open(temp_dir.path())?.stats();
+    assert_eq!(stats.disk_quota, None);
+    assert!(stats.disk_bytes > 0);
+
+    Ok(())
+}
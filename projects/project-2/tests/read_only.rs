@@ -0,0 +1,146 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::PredicateStrExt;
+use project_2::{KvStore, KvsError};
+use std::path::Path;
+use tempfile::TempDir;
+
+fn log_files(path: &Path) -> usize {
+    std::fs::read_dir(path)
+        .unwrap()
+        .filter(|entry| {
+            let path = entry.as_ref().unwrap().path();
+            path.extension() == Some("log".as_ref())
+        })
+        .count()
+}
+
+// A read-only store should serve reads, refuse writes, and leave no trace on disk.
+#[test]
+fn read_only_refuses_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    store.keyspace("sessions").set("key1", "value1")?;
+    drop(store);
+
+    let segments = log_files(temp_dir.path());
+    let store = KvStore::open_read_only(temp_dir.path())?;
+    assert!(store.is_read_only());
+    assert_eq!(store.get("key1")?, Some("value1".to_owned()));
+
+    assert!(matches!(
+        store.set("key2", "value2"),
+        Err(KvsError::ReadOnly)
+    ));
+    assert!(matches!(store.remove("key1"), Err(KvsError::ReadOnly)));
+    assert!(matches!(store.remove("missing"), Err(KvsError::ReadOnly)));
+    assert!(matches!(
+        store.drop_keyspace("sessions"),
+        Err(KvsError::ReadOnly)
+    ));
+    assert_eq!(log_files(temp_dir.path()), segments);
+
+    Ok(())
+}
+
+// Opening a missing directory read-only should fail rather than create it.
+#[test]
+fn read_only_requires_existing_dir() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path().join("missing");
+
+    assert!(KvStore::open_read_only(&path).is_err());
+    assert!(!path.exists());
+}
+
+// Several readers should be able to follow a live writer through `refresh`,
+// including across compactions.
+#[test]
+fn readers_follow_writer() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let writer = KvStore::open(temp_dir.path())?;
+    writer.set("key1", "value1")?;
+    writer.set("doomed", "value")?;
+
+    let reader1 = KvStore::open_read_only(temp_dir.path())?;
+    let reader2 = KvStore::options()
+        .read_only(true)
+        .mmap(true)
+        .open(temp_dir.path())?;
+    assert_eq!(reader1.get("key1")?, Some("value1".to_owned()));
+
+    writer.set("key2", "value2")?;
+    assert_eq!(reader1.get("key2")?, None);
+    reader1.refresh()?;
+    reader2.refresh()?;
+    assert_eq!(reader1.get("key2")?, Some("value2".to_owned()));
+    assert_eq!(reader2.get("key2")?, Some("value2".to_owned()));
+
+    writer.remove("doomed")?;
+    for iter in 0..200 {
+        for key_id in 0..100 {
+            writer.set(&format!("key{}", key_id), &format!("{}", iter))?;
+        }
+    }
+
+    for reader in [&reader1, &reader2] {
+        reader.refresh()?;
+        assert_eq!(reader.get("doomed")?, None);
+        assert_eq!(reader.get("key42")?, Some("199".to_owned()));
+        assert_eq!(reader.stats().keys, writer.stats().keys);
+    }
+
+    Ok(())
+}
+
+// A reader cannot reopen segments the writer has compacted away, so it should
+// keep them all open until it refreshes, whatever the open segment limit.
+#[test]
+fn readers_keep_segments_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let writer = KvStore::options()
+        .max_segment_size(32)
+        .auto_compact(false)
+        .open(temp_dir.path())?;
+    for i in 0..10 {
+        writer.set(&format!("key{i}"), &format!("value{i}"))?;
+    }
+
+    let reader = KvStore::options()
+        .read_only(true)
+        .max_open_segments(1)
+        .open(temp_dir.path())?;
+    writer.compact()?;
+
+    for i in 0..10 {
+        assert_eq!(reader.get(&format!("key{i}"))?, Some(format!("value{i}")));
+    }
+
+    Ok(())
+}
+
+// `kvs get` only reads, so it should work while another process owns the
+// store, and should not add a segment.
+#[test]
+fn cli_get_is_read_only() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    let segments = log_files(temp_dir.path());
+
+    kvs()
+        .args(["get", "key1"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+
+    assert_eq!(log_files(temp_dir.path()), segments);
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::contains;
+use project_2::{Fault, FaultyVfs, KvStore, KvsError, SyncPolicy};
+use regex::Regex;
+use tempfile::TempDir;
+
+#[test]
+fn keys_matching() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    for key in ["user:1", "user:22", "user:x", "admin:1"] {
+        store.set(key, "value")?;
+    }
+    store.keyspace("other").set("user:3", "value")?;
+
+    let regex = Regex::new(r"^user:\d+$")?;
+    assert_eq!(store.keys_matching(&regex), ["user:1", "user:22"]);
+    assert_eq!(store.keyspace("other").keys_matching(&regex), ["user:3"]);
+    assert!(store.keyspace("missing").keys_matching(&regex).is_empty());
+
+    Ok(())
+}
+
+// Batch removal should only touch matching keys in its own keyspace, and survive a reopen.
+#[test]
+fn remove_matching() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    for key_id in 0..100 {
+        store.set(&format!("user:{key_id}"), "value")?;
+    }
+    store.set("admin:1", "value")?;
+    store.keyspace("other").set("user:1", "value")?;
+
+    let regex = Regex::new(r"^user:\d+$")?;
+    assert_eq!(store.remove_matching(&regex)?, 100);
+    assert_eq!(store.remove_matching(&regex)?, 0);
+    assert_eq!(store.keys(), ["admin:1"]);
+    drop(store);
+
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.keys(), ["admin:1"]);
+    assert_eq!(store.keyspace("other").keys(), ["user:1"]);
+
+    Ok(())
+}
+
+// A removal that fails part way should say how many keys were already removed.
+#[test]
+fn remove_matching_reports_partial_removal() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options()
+        .vfs(vfs.clone())
+        .sync(SyncPolicy::Never)
+        .open("/store")?;
+    for key_id in 0..3 {
+        store.set(&format!("user:{key_id}"), "value")?;
+    }
+
+    // Each removal is a single write, so the second one fails.
+    vfs.inject(Fault::DiskFull, vfs.ops() + 1);
+    let result = store.remove_matching(&Regex::new("^user:")?);
+    assert!(matches!(
+        result,
+        Err(KvsError::PartiallyRemoved { removed: 1, ref source }) if matches!(**source, KvsError::DiskFull)
+    ));
+    assert_eq!(
+        result.unwrap_err().exit_code(),
+        KvsError::DiskFull.exit_code()
+    );
+    assert_eq!(store.keys(), ["user:1", "user:2"]);
+
+    Ok(())
+}
+
+#[test]
+fn cli_keys_regex() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    for (key, value) in [("user:1", "alice"), ("user:x", "bob"), ("team:1", "red")] {
+        kvs()
+            .args(["set", key, value])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+    }
+
+    kvs()
+        .args(["keys", "--regex", r":\d+$"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("team:1\nuser:1\n"));
+
+    kvs()
+        .args(["keys", "--regex", r":\d+$", "--prefix", "user", "--values"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("user:1\talice\n"));
+
+    kvs()
+        .args(["keys", "--regex", "("])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("--regex"));
+
+    kvs()
+        .args(["keys", "--regex", "^user:", "--delete"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("Removed 2 keys"));
+
+    kvs()
+        .args(["keys"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("team:1\n"));
+}
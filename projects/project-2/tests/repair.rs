@@ -0,0 +1,88 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::str::contains;
+use project_2::{KvStore, KvsError};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+};
+use tempfile::TempDir;
+
+// Writes in separate sessions, so that each lands in its own segment.
+fn seed(dir: &TempDir) -> Result<()> {
+    for (key, value) in [("key1", "value1"), ("key2", "value2"), ("key3", "value3")] {
+        KvStore::open(dir.path())?.set(key, value)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn repair_damaged_segment() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    seed(&temp_dir)?;
+
+    let mut log = OpenOptions::new()
+        .append(true)
+        .open(temp_dir.path().join("2.log"))?;
+    log.write_all(&[9, 9, 9, 9])?;
+    fs::write(temp_dir.path().join("4.log.compact"), "partial")?;
+
+    let report = KvStore::repair(temp_dir.path())?;
+    assert_eq!(report.keys, 3);
+    assert_eq!(report.salvaged_records, 1);
+    assert_eq!(report.lost_bytes, 4);
+    assert_eq!(report.quarantined.len(), 2);
+    for file in &report.quarantined {
+        assert!(file.starts_with("lost+found"));
+        assert!(temp_dir.path().join(file).exists());
+    }
+
+    assert!(KvStore::verify(temp_dir.path())?.is_ok());
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("key2")?, Some("value2".to_owned()));
+    assert_eq!(store.get("key3")?, Some("value3".to_owned()));
+
+    let result = KvStore::repair(temp_dir.path());
+    assert!(matches!(result, Err(KvsError::Locked { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn repair_healthy_store() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    seed(&temp_dir)?;
+
+    let report = KvStore::repair(temp_dir.path())?;
+    assert_eq!(report.keys, 3);
+    assert!(report.quarantined.is_empty());
+    assert!(!temp_dir.path().join("lost+found").exists());
+
+    Ok(())
+}
+
+#[test]
+fn cli_repair() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    seed(&temp_dir)?;
+    fs::write(temp_dir.path().join("1.log"), [1, 2, 3])?;
+
+    kvs()
+        .args(["repair"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("Recovered 2 keys"))
+        .stdout(contains("losing 3 bytes"));
+
+    kvs()
+        .args(["verify"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success();
+
+    Ok(())
+}
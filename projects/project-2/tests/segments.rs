@@ -0,0 +1,55 @@
+use anyhow::Result;
+use project_2::{KvStore, KvsError};
+use std::fs;
+use tempfile::TempDir;
+
+fn check_keys(store: &KvStore) -> Result<()> {
+    for i in 0..50 {
+        assert_eq!(store.get(&format!("key{i}"))?, Some(format!("value{i}")));
+    }
+    Ok(())
+}
+
+#[test]
+fn reopens_closed_segments() -> Result<()> {
+    for mmap in [false, true] {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let mut options = KvStore::options();
+        options.max_open_segments(2).max_segment_size(32).mmap(mmap);
+
+        let store = options.open(temp_dir.path())?;
+        for i in 0..50 {
+            store.set(&format!("key{i}"), &format!("value{i}"))?;
+        }
+        check_keys(&store)?;
+        assert!(fs::read_dir(temp_dir.path())?.count() > 10);
+        drop(store);
+
+        let store = options.open(temp_dir.path())?;
+        check_keys(&store)?;
+        store.compact()?;
+        check_keys(&store)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn missing_segment_is_an_error() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::options()
+        .max_open_segments(1)
+        .max_segment_size(1)
+        .open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    store.set("key2", "value2")?;
+
+    fs::remove_file(temp_dir.path().join("1.log"))?;
+    assert!(matches!(
+        store.get("key1"),
+        Err(KvsError::SegmentNotFound(1))
+    ));
+    assert_eq!(store.get("key2")?, Some("value2".to_owned()));
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::ord::eq;
+use predicates::str::contains;
+use std::fs;
+use tempfile::TempDir;
+
+// Piped input should run through the same commands as the interactive prompt.
+#[test]
+fn cli_shell() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+
+    kvs()
+        .args(["shell", "--dir"])
+        .arg(&store_dir)
+        .env("HOME", temp_dir.path())
+        .with_stdin()
+        .buffer("set key1 \"value with spaces\"\nget key1\nget missing\nscan\nexit\nget key1\n")
+        .assert()
+        .success()
+        .stdout(contains("value with spaces\nkey1\tvalue with spaces\n"))
+        .stderr(contains("Key not found"));
+
+    assert!(temp_dir.path().join(".kvs_history").exists());
+}
+
+#[test]
+fn cli_shell_batch() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store_dir = temp_dir.path().join("store");
+    let script = temp_dir.path().join("script");
+    fs::write(
+        &script,
+        "# Seed some keys\nset key1 value1\n\nset key2 value2\nrm key1\ncount\n",
+    )
+    .unwrap();
+
+    kvs()
+        .args(["shell", "--batch"])
+        .arg(&script)
+        .arg("--dir")
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(eq("1\n"));
+
+    // The first failing line stops the script and is reported with its line number.
+    fs::write(&script, "set key3 value3\nrm key1\nset key4 value4\n").unwrap();
+    kvs()
+        .args(["shell", "--batch"])
+        .arg(&script)
+        .arg("--dir")
+        .arg(&store_dir)
+        .assert()
+        .failure()
+        .stderr(contains(":2: rm key1"))
+        .stderr(contains("Key not found"));
+
+    kvs()
+        .args(["keys", "--dir"])
+        .arg(&store_dir)
+        .assert()
+        .success()
+        .stdout(eq("key2\nkey3\n"));
+}
@@ -1,24 +1,25 @@
+mod common;
+
 use anyhow::Result;
 use assert_cmd::prelude::*;
+use common::kvs;
 use predicates::ord::eq;
 use predicates::str::{contains, is_empty, PredicateStrExt};
 use project_2::KvStore;
-use std::process::Command;
 use tempfile::TempDir;
 use walkdir::WalkDir;
 
 // `kvs` with no args should exit with a non-zero code.
 #[test]
 fn cli_no_args() {
-    Command::cargo_bin("project-2").unwrap().assert().failure();
+    kvs().assert().failure();
 }
 
 // `kvs -V` should print the version
 #[test]
 fn cli_version() {
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["-V"])
+    kvs()
+        .args(["-V"])
         .assert()
         .stdout(contains(env!("CARGO_PKG_VERSION")));
 }
@@ -27,9 +28,8 @@ fn cli_version() {
 #[test]
 fn cli_get_non_existent_key() {
     let temp_dir = TempDir::new().unwrap();
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["get", "key1"])
+    kvs()
+        .args(["get", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .failure()
@@ -40,9 +40,8 @@ fn cli_get_non_existent_key() {
 #[test]
 fn cli_rm_non_existent_key() {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["rm", "key1"])
+    kvs()
+        .args(["rm", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .failure()
@@ -53,9 +52,8 @@ fn cli_rm_non_existent_key() {
 #[test]
 fn cli_set() {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["set", "key1", "value1"])
+    kvs()
+        .args(["set", "key1", "value1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -66,22 +64,20 @@ fn cli_set() {
 fn cli_get_stored() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
 
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     store.set("key1", "value1")?;
     store.set("key2", "value2")?;
     drop(store);
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["get", "key1"])
+    kvs()
+        .args(["get", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
         .stdout(eq("value1").trim());
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["get", "key2"])
+    kvs()
+        .args(["get", "key2"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -95,21 +91,19 @@ fn cli_get_stored() -> Result<()> {
 fn cli_rm_stored() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
 
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     store.set("key1", "value1")?;
     drop(store);
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["rm", "key1"])
+    kvs()
+        .args(["rm", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
         .stdout(is_empty());
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["get", "key1"])
+    kvs()
+        .args(["get", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .failure()
@@ -120,69 +114,40 @@ fn cli_rm_stored() -> Result<()> {
 
 #[test]
 fn cli_invalid_get() {
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["get"])
-        .assert()
-        .failure();
+    kvs().args(["get"]).assert().failure();
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["get", "extra", "field"])
-        .assert()
-        .failure();
+    kvs().args(["get", "extra", "field"]).assert().failure();
 }
 
 #[test]
 fn cli_invalid_set() {
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["set"])
-        .assert()
-        .failure();
+    kvs().args(["set"]).assert().failure();
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["set", "missing_field"])
-        .assert()
-        .failure();
+    kvs().args(["set", "missing_field"]).assert().failure();
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["set", "extra", "extra", "field"])
+    kvs()
+        .args(["set", "extra", "extra", "field"])
         .assert()
         .failure();
 }
 
 #[test]
 fn cli_invalid_rm() {
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["rm"])
-        .assert()
-        .failure();
+    kvs().args(["rm"]).assert().failure();
 
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["rm", "extra", "field"])
-        .assert()
-        .failure();
+    kvs().args(["rm", "extra", "field"]).assert().failure();
 }
 
 #[test]
 fn cli_invalid_subcommand() {
-    Command::cargo_bin("project-2")
-        .unwrap()
-        .args(&["unknown", "subcommand"])
-        .assert()
-        .failure();
+    kvs().args(["unknown", "subcommand"]).assert().failure();
 }
 
 // Should get previously stored value.
 #[test]
 fn get_stored_value() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
 
     store.set("key1", "value1")?;
     store.set("key2", "value2")?;
@@ -192,7 +157,7 @@ fn get_stored_value() -> Result<()> {
 
     // Open from disk again and check persistent data.
     drop(store);
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     assert_eq!(store.get("key1")?, Some("value1".to_owned()));
     assert_eq!(store.get("key2")?, Some("value2".to_owned()));
 
@@ -203,7 +168,7 @@ fn get_stored_value() -> Result<()> {
 #[test]
 fn overwrite_value() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
 
     store.set("key1", "value1")?;
     assert_eq!(store.get("key1")?, Some("value1".to_owned()));
@@ -212,7 +177,7 @@ fn overwrite_value() -> Result<()> {
 
     // Open from disk again and check persistent data.
     drop(store);
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     assert_eq!(store.get("key1")?, Some("value2".to_owned()));
     store.set("key1", "value3")?;
     assert_eq!(store.get("key1")?, Some("value3".to_owned()));
@@ -224,14 +189,14 @@ fn overwrite_value() -> Result<()> {
 #[test]
 fn get_non_existent_value() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
 
     store.set("key1", "value1")?;
     assert_eq!(store.get("key2")?, None);
 
     // Open from disk again and check persistent data.
     drop(store);
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     assert_eq!(store.get("key2")?, None);
 
     Ok(())
@@ -240,7 +205,7 @@ fn get_non_existent_value() -> Result<()> {
 #[test]
 fn remove_non_existent_key() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     assert!(store.remove("key1").is_err());
     Ok(())
 }
@@ -248,7 +213,7 @@ fn remove_non_existent_key() -> Result<()> {
 #[test]
 fn remove_key() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
     store.set("key1", "value1")?;
     assert!(store.remove("key1").is_ok());
     assert_eq!(store.get("key1")?, None);
@@ -260,7 +225,7 @@ fn remove_key() -> Result<()> {
 #[test]
 fn compaction() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
-    let mut store = KvStore::open(temp_dir.path())?;
+    let store = KvStore::open(temp_dir.path())?;
 
     let dir_size = || {
         let entries = WalkDir::new(temp_dir.path()).into_iter();
@@ -290,7 +255,7 @@ fn compaction() -> Result<()> {
 
         drop(store);
         // reopen and check content.
-        let mut store = KvStore::open(temp_dir.path())?;
+        let store = KvStore::open(temp_dir.path())?;
         for key_id in 0..1000 {
             let key = format!("key{}", key_id);
             assert_eq!(store.get(&key)?, Some(format!("{}", iter)));
@@ -0,0 +1,141 @@
+mod common;
+
+use anyhow::Result;
+use assert_cmd::prelude::*;
+use common::kvs;
+use predicates::str::contains;
+use project_2::{KvStore, KvsError, Problem};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+use tempfile::TempDir;
+
+#[test]
+fn healthy_store() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    for iter in 0..10 {
+        store.set("key1", &format!("value{iter}"))?;
+    }
+    store.keyspace("sessions").set("key2", "session")?;
+    store.compact()?;
+    store.set("key3", "old")?;
+    store.set("key3", "value3")?;
+
+    let result = KvStore::verify(temp_dir.path());
+    assert!(matches!(result, Err(KvsError::Locked { .. })));
+    drop(store);
+
+    let report = KvStore::verify(temp_dir.path())?;
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.live_records, 3);
+    assert_eq!(report.records, 4);
+    assert!(report.unreachable_bytes > 0);
+
+    Ok(())
+}
+
+// An open store's index should be checked against the segments as they
+// are on disk, not as they were when it was built.
+#[test]
+fn check_index() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    store.keyspace("sessions").set("key2", "session")?;
+    store.compact()?;
+    store.set("key3", "value3")?;
+    assert_eq!(store.check_index()?, []);
+
+    let active = fs::read_dir(temp_dir.path())?
+        .filter_map(|entry| {
+            entry
+                .ok()?
+                .file_name()
+                .to_str()?
+                .strip_suffix(".log")?
+                .parse()
+                .ok()
+        })
+        .max()
+        .unwrap();
+    fs::write(temp_dir.path().join(format!("{active}.log")), "")?;
+    assert_eq!(
+        store.check_index()?,
+        [Problem::BadPointer {
+            keyspace: "default".to_owned(),
+            key: "key3".to_owned(),
+            seq: active,
+            offset: 0,
+        }]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reports_problems() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1", "value1")?;
+    drop(store);
+
+    let mut log = OpenOptions::new()
+        .append(true)
+        .open(temp_dir.path().join("1.log"))?;
+    log.write_all(&[7, 7, 7])?;
+    let size = log.metadata()?.len();
+    fs::write(temp_dir.path().join("01.log"), "")?;
+    fs::write(temp_dir.path().join("5.log.compact"), "")?;
+    fs::write(temp_dir.path().join("notes.txt"), "")?;
+
+    let report = KvStore::verify(temp_dir.path())?;
+    assert_eq!(
+        report.problems,
+        [
+            Problem::BadSegmentName {
+                file: PathBuf::from("01.log")
+            },
+            Problem::OrphanFile {
+                file: PathBuf::from("5.log.compact")
+            },
+            Problem::StrayFile {
+                file: PathBuf::from("notes.txt")
+            },
+            Problem::CorruptRange {
+                seq: 1,
+                start: size - 3,
+                end: size
+            },
+        ]
+    );
+    assert_eq!(report.live_records, 1);
+
+    Ok(())
+}
+
+#[test]
+fn cli_verify() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    KvStore::open(temp_dir.path())?.set("key1", "value1")?;
+
+    kvs()
+        .args(["verify"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("records: 1 (1 live)"));
+
+    fs::write(temp_dir.path().join("notes.txt"), "")?;
+    kvs()
+        .args(["verify"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .code(42)
+        .stdout(contains("problem: notes.txt"));
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use project_2::{Fault, FaultyVfs, KvStore, KvsError, MemoryVfs, SyncPolicy, Vfs};
+use std::{collections::BTreeMap, path::Path};
+
+const STORE: &str = "/store";
+
+#[test]
+fn memory_store() -> Result<()> {
+    let vfs = MemoryVfs::new();
+    let store = KvStore::options().vfs(vfs.clone()).open(STORE)?;
+    for i in 0..100 {
+        store.set(&format!("key{i}"), &format!("value{i}"))?;
+    }
+    store.remove("key0")?;
+    store.compact()?;
+    drop(store);
+
+    assert!(!Path::new(STORE).exists());
+    assert!(vfs.exists(&Path::new(STORE).join("engine")));
+
+    let store = KvStore::options().vfs(vfs).open(STORE)?;
+    assert_eq!(store.get("key0")?, None);
+    assert_eq!(store.get("key99")?, Some("value99".to_owned()));
+    assert_eq!(store.keys().len(), 99);
+
+    Ok(())
+}
+
+#[test]
+fn short_writes_are_retried() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options().vfs(vfs.clone()).open(STORE)?;
+    for at in vfs.ops()..vfs.ops() + 20 {
+        vfs.inject(Fault::ShortWrite, at);
+    }
+
+    for i in 0..20 {
+        store.set(&format!("key{i}"), &"x".repeat(100))?;
+    }
+    drop(store);
+
+    let store = KvStore::options().vfs(vfs).open(STORE)?;
+    assert_eq!(store.keys().len(), 20);
+    assert_eq!(store.get("key19")?, Some("x".repeat(100)));
+
+    Ok(())
+}
+
+#[test]
+fn disk_full_and_sync_failures() -> Result<()> {
+    let vfs = FaultyVfs::new();
+    let store = KvStore::options()
+        .vfs(vfs.clone())
+        .sync(SyncPolicy::Always)
+        .open(STORE)?;
+
+    vfs.inject(Fault::DiskFull, vfs.ops());
+    let result = store.set("key1", "value1");
+    assert!(matches!(result, Err(KvsError::DiskFull)));
+
+    vfs.inject(Fault::SyncFailure, vfs.ops());
+    assert!(store.set("key2", "value2").is_err());
+    assert!(!vfs.has_crashed());
+
+    Ok(())
+}
+
+/// The writes a workload made before it crashed.
+#[derive(Default)]
+struct Writes {
+    acknowledged: BTreeMap<String, String>,
+    /// The write that failed, which may or may not have made it to disk.
+    in_flight: Option<(String, String)>,
+}
+
+/// Writes and compacts, recording every write that was acknowledged.
+fn workload(vfs: &FaultyVfs, writes: &mut Writes) -> project_2::Result<()> {
+    let store = KvStore::options()
+        .vfs(vfs.clone())
+        .sync(SyncPolicy::Always)
+        .auto_compact(false)
+        .max_segment_size(64)
+        .open(STORE)?;
+
+    for round in 0..3 {
+        for i in 0..4 {
+            let (key, value) = (format!("key{i}"), format!("value{i}-{round}"));
+            writes.in_flight = Some((key.clone(), value.clone()));
+            store.set(&key, &value)?;
+            writes.in_flight = None;
+            writes.acknowledged.insert(key, value);
+        }
+        store.compact()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn recovers_from_crash_at_every_point() -> Result<()> {
+    let clean = FaultyVfs::new();
+    workload(&clean, &mut Writes::default())?;
+    let ops = clean.ops();
+
+    for at in 0..ops {
+        let vfs = FaultyVfs::new();
+        vfs.inject(Fault::Crash, at);
+
+        let mut writes = Writes::default();
+        assert!(workload(&vfs, &mut writes).is_err());
+        assert!(vfs.has_crashed());
+
+        let store = KvStore::options().vfs(vfs.after_crash()).open(STORE)?;
+        for (key, value) in &writes.acknowledged {
+            let found = store.get(key)?;
+            let in_flight =
+                writes
+                    .in_flight
+                    .as_ref()
+                    .is_some_and(|(in_flight_key, in_flight_value)| {
+                        in_flight_key == key && found.as_ref() == Some(in_flight_value)
+                    });
+            assert!(
+                found.as_ref() == Some(value) || in_flight,
+                "lost {key} after a crash at operation {at}"
+            );
+        }
+    }
+
+    Ok(())
+}